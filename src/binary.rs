@@ -1,36 +1,342 @@
 use anyhow::{anyhow, Context};
-use goblin::{elf, Object};
+use goblin::container::{Container, Ctx, Endian};
+use goblin::elf::compression_header::CompressionHeader;
+use goblin::mach::constants::cputype::{CPU_TYPE_X86, CPU_TYPE_X86_64};
+use goblin::mach::constants::{
+    S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS,
+};
+use goblin::mach::symbols::{N_PEXT, N_SECT};
+use goblin::{elf, mach, pe, Object};
 use iced_x86::{
-    CpuidFeature, Decoder, DecoderOptions, Instruction, Mnemonic,
+    CpuidFeature, Decoder, DecoderOptions, EncodingKind, Instruction,
+    Mnemonic, OpKind, RoundingControl,
 };
+use scroll::Pwrite;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::io::Read;
+use std::iter::Sum;
+use std::ops::{AddAssign, Range};
 use std::path::Path;
 
+use crate::compression;
+use crate::jit;
+use crate::kernel;
+
+/// Decoder options that can be enabled on the command line, named after
+/// the corresponding constants in `iced_x86::DecoderOptions`.
+pub const DECODER_OPTIONS: &[(&str, u32)] = &[
+    ("no-invalid-check", DecoderOptions::NO_INVALID_CHECK),
+    ("amd", DecoderOptions::AMD),
+    ("force-reserved-nop", DecoderOptions::FORCE_RESERVED_NOP),
+    ("umov", DecoderOptions::UMOV),
+    ("xbts", DecoderOptions::XBTS),
+    ("cmpxchg486a", DecoderOptions::CMPXCHG486A),
+    ("old-fpu", DecoderOptions::OLD_FPU),
+    ("pcommit", DecoderOptions::PCOMMIT),
+    ("loadall286", DecoderOptions::LOADALL286),
+    ("loadall386", DecoderOptions::LOADALL386),
+    ("cl1invmb", DecoderOptions::CL1INVMB),
+    ("mov-tr", DecoderOptions::MOV_TR),
+    ("jmpe", DecoderOptions::JMPE),
+    ("no-pause", DecoderOptions::NO_PAUSE),
+    ("no-wbnoinvd", DecoderOptions::NO_WBNOINVD),
+    ("udbg", DecoderOptions::UDBG),
+    ("no-mpfx-0fbc", DecoderOptions::NO_MPFX_0FBC),
+    ("no-mpfx-0fbd", DecoderOptions::NO_MPFX_0FBD),
+    ("no-lahf-sahf-64", DecoderOptions::NO_LAHF_SAHF_64),
+    ("mpx", DecoderOptions::MPX),
+    ("cyrix", DecoderOptions::CYRIX),
+    ("cyrix-smint-0f7e", DecoderOptions::CYRIX_SMINT_0F7E),
+    ("cyrix-dmi", DecoderOptions::CYRIX_DMI),
+    ("altinst", DecoderOptions::ALTINST),
+    ("knc", DecoderOptions::KNC),
+];
+
+/// Instruction counts and sizes keyed by mnemonic, extension sets, and
+/// (if requested) encoding.
+type Counts = HashMap<
+    (Mnemonic, &'static [CpuidFeature], Option<EncodingKind>),
+    Usage,
+>;
+
+/// Same as `Counts` but also keyed by symbol name.
+type SymbolCounts<'a> = HashMap<
+    (
+        &'a str,
+        Mnemonic,
+        &'static [CpuidFeature],
+        Option<EncodingKind>,
+    ),
+    Usage,
+>;
+
+/// Number of instructions and their total length in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.count += other.count;
+        self.bytes += other.bytes;
+    }
+}
+
+impl Sum for Usage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Usage::default(), |mut result, usage| {
+            result += usage;
+            result
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DecodedInstruction {
+    pub address: usize,
+    /// Length of the instruction in bytes.
+    pub length: usize,
+    pub mnemonic: Mnemonic,
+    pub features: &'static [CpuidFeature],
+    pub encoding: EncodingKind,
+    /// Width in bits (128, 256, or 512) of the widest vector register
+    /// or packed memory operand, or `None` if the instruction has
+    /// neither.
+    pub vector_width: Option<u32>,
+    /// Uses an opmask register (`k1`-`k7`).
+    pub masking: bool,
+    pub zeroing_masking: bool,
+    pub broadcast: bool,
+    /// Uses static rounding or suppresses all exceptions (SAE).
+    pub rounding: bool,
+}
+
+/// Number of vector instructions by register width and by use of
+/// EVEX-only facilities.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct VectorUsage {
+    #[serde(rename = "128")]
+    pub width_128: usize,
+    #[serde(rename = "256")]
+    pub width_256: usize,
+    #[serde(rename = "512")]
+    pub width_512: usize,
+    pub masking: usize,
+    pub zeroing_masking: usize,
+    pub broadcast: usize,
+    pub rounding: usize,
+}
+
+impl VectorUsage {
+    fn add(&mut self, instruction: &DecodedInstruction) {
+        match instruction.vector_width {
+            Some(128) => self.width_128 += 1,
+            Some(256) => self.width_256 += 1,
+            Some(512) => self.width_512 += 1,
+            _ => {}
+        }
+
+        self.masking += usize::from(instruction.masking);
+        self.zeroing_masking +=
+            usize::from(instruction.zeroing_masking);
+        self.broadcast += usize::from(instruction.broadcast);
+        self.rounding += usize::from(instruction.rounding);
+    }
+}
+
+/// Code section that is decoded with a single bitness.
+#[derive(Debug)]
+struct Section {
+    name: String,
+    address: usize,
+    bitness: u32,
+    data: Vec<u8>,
+}
+
+/// Location of a symbol's code relative to the beginning of its
+/// section.
+#[derive(Debug)]
+struct SymbolRange {
+    section: usize,
+    begin: usize,
+    end: usize,
+    bitness: u32,
+}
+
+/// Contiguous range of code that is decoded with a single bitness.
+#[derive(Debug)]
+pub struct Region<'a> {
+    /// Name of the section, or of the symbol if its bitness was
+    /// overridden.
+    pub name: &'a str,
+    pub address: usize,
+    pub bitness: u32,
+    code: &'a [u8],
+}
+
+impl Region<'_> {
+    pub fn size(&self) -> usize {
+        self.code.len()
+    }
+}
+
 #[derive(Debug)]
 pub struct Binary {
+    /// Container format, e.g., `elf` or `raw`.
+    format: &'static str,
+    /// SHA-256 digest of the input, in hexadecimal.
+    sha256: String,
     bitness: u32,
-    text: Vec<u8>,
-    symbols: HashMap<String, (usize, usize)>,
+    decoder_options: u32,
+    sections: Vec<Section>,
+    /// Symbols can share a name, e.g., static functions in different
+    /// source files or the alternatives of a kernel function.
+    symbols: Vec<(String, SymbolRange)>,
+    /// Functions exported in `.dynsym`.
+    exported: HashSet<String>,
+    /// Addresses of `call` operands that are relocated against
+    /// `kernel_fpu_begin` in a kernel module.
+    fpu_calls: HashSet<usize>,
 }
 
 impl Binary {
-    pub fn from_file<P>(path: P) -> anyhow::Result<Self>
+    pub fn from_file<P>(
+        path: P,
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self>
     where
         P: AsRef<Path>,
     {
-        let data = std::fs::read(path)?;
+        Self::from_bytes(
+            &std::fs::read(path)?,
+            decoder_options,
+            bitness_overrides,
+        )
+    }
 
-        let Object::Elf(elf) = Object::parse(&data)? else {
-            return Err(anyhow!("Only ELF binaries are supported."));
+    /// Detects whether `data` is an ELF, PE, or Mach-O binary or a
+    /// jitdump file, after decompressing it if it is compressed with
+    /// gzip, xz, or zstd.
+    pub fn from_bytes(
+        data: &[u8],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        // The digest is of the input as given, e.g., compressed, so
+        // that it can be compared with the output of `sha256sum`.
+        //
+        let digest = sha256(data);
+        let decompressed = compression::decompress(data)?;
+        let data = decompressed.as_deref().unwrap_or(data);
+        let expanded = if data.starts_with(elf::header::ELFMAG) {
+            decompress_elf_sections(data)?
+        } else {
+            None
         };
+        let data = expanded.as_deref().unwrap_or(data);
+        let mut binary = if jit::is_jitdump(data) {
+            Self::from_jitdump(
+                data,
+                decoder_options,
+                bitness_overrides,
+            )?
+        } else {
+            Self::from_object(
+                &Object::parse(data)?,
+                data,
+                decoder_options,
+                bitness_overrides,
+            )?
+        };
+
+        binary.sha256 = digest;
+
+        Ok(binary)
+    }
+
+    /// Dispatches to `from_elf`, `from_pe`, or `from_mach` depending on
+    /// the format of `object`.  For universal Mach-O binaries, the
+    /// x86-64 slice is preferred over the i386 slice.
+    pub fn from_object(
+        object: &Object,
+        data: &[u8],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        match object {
+            Object::Elf(elf) => Self::from_elf(
+                elf,
+                data,
+                decoder_options,
+                bitness_overrides,
+            ),
+            Object::PE(pe) => Self::from_pe(
+                pe,
+                data,
+                decoder_options,
+                bitness_overrides,
+            ),
+            Object::Mach(mach::Mach::Binary(macho)) => Self::from_mach(
+                macho,
+                decoder_options,
+                bitness_overrides,
+            ),
+            Object::Mach(mach::Mach::Fat(multi)) => {
+                let index = [CPU_TYPE_X86_64, CPU_TYPE_X86]
+                    .into_iter()
+                    .find_map(|cputype| {
+                        multi.iter_arches().position(|arch| {
+                            arch.is_ok_and(|x| x.cputype == cputype)
+                        })
+                    })
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Universal binary does not contain x86 code"
+                        )
+                    })?;
+                let mach::SingleArch::MachO(macho) =
+                    multi.get(index)?
+                else {
+                    return Err(anyhow!(
+                        "Universal binary contains an archive instead \
+                         of x86 code"
+                    ));
+                };
 
-        Self::from_elf(&elf, &data)
+                Self::from_mach(
+                    &macho,
+                    decoder_options,
+                    bitness_overrides,
+                )
+            }
+            _ => Err(anyhow!(
+                "Only ELF, PE, and Mach-O binaries are supported."
+            )),
+        }
     }
 
+    /// Reads the `.text` section, sections whose names indicate 16-,
+    /// 32-, or 64-bit code (e.g., `.text16` or `.code32`), and sections
+    /// listed in `bitness_overrides`.  Entries of `bitness_overrides`
+    /// that do not name a section are applied to the symbol with that
+    /// name.
+    ///
+    /// In relocatable objects and in the Linux kernel, all executable
+    /// sections are read, e.g., `.init.text` and `.altinstr_replacement`.
+    /// The replacement instructions of the kernel's alternatives are
+    /// named after the function that they are patched into, e.g.,
+    /// `memcpy (alternative)`.
     pub fn from_elf(
         elf: &elf::Elf,
         data: &[u8],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
     ) -> anyhow::Result<Self> {
         let bitness = match elf.header.e_machine {
             elf::header::EM_386 => 32,
@@ -43,249 +349,1548 @@ impl Binary {
             }
         };
 
+        let ctx = Ctx::new(
+            if elf.is_64 {
+                Container::Big
+            } else {
+                Container::Little
+            },
+            if elf.little_endian {
+                Endian::Little
+            } else {
+                Endian::Big
+            },
+        );
+
+        let is_relocatable = elf.header.e_type == elf::header::ET_REL;
+        let is_kernel = kernel::is_kernel(elf);
+
         // The elf(5) man page lists the sections contained in a binary.
         // The `.text` section contains the executable instructions of
         // the program.
-        let Some(text_hdr) =
-            elf.section_headers.iter().find(|&section| {
-                elf.shdr_strtab.get_at(section.sh_name) == Some(".text")
-            })
-        else {
-            return Err(anyhow!(
-                "Binary does not contain a '.text' section"
-            ));
-        };
+        let mut sections = Vec::new();
+        // Index of each section header that is read, mapped to the
+        // address of the section.
+        //
+        let mut addresses = HashMap::new();
 
-        let text_begin = usize::try_from(text_hdr.sh_offset)
-            .with_context(|| {
-                format!(
-                    "The '.text' section has offset {} which is \
-                 greater than usize::MAX on this platform",
-                    text_hdr.sh_offset
-                )
-            })?;
-
-        let text_end = text_begin
-            + usize::try_from(text_hdr.sh_size).with_context(|| {
-                format!(
-                    "The '.text' section has size {} which is greater \
-                 than usize::MAX on this platform",
-                    text_hdr.sh_size
-                )
-            })?;
+        for (index, section_hdr) in
+            elf.section_headers.iter().enumerate()
+        {
+            let Some(name) =
+                elf.shdr_strtab.get_at(section_hdr.sh_name)
+            else {
+                continue;
+            };
+
+            let section_bitness = bitness_overrides
+                .iter()
+                .find(|(x, _)| x == name)
+                .map(|&(_, bits)| bits)
+                .or_else(|| bitness_from_section_name(name))
+                .or_else(|| (name == ".text").then_some(bitness))
+                .or_else(|| {
+                    ((is_relocatable || is_kernel)
+                        && kernel::is_code(section_hdr))
+                    .then_some(bitness)
+                });
+
+            // Sections of type `SHT_NOBITS` do not occupy space in the
+            // file, e.g., `.text` in a separate debug file.
+            //
+            if section_hdr.sh_type == elf::section_header::SHT_NOBITS {
+                continue;
+            }
+
+            if let Some(section_bitness) = section_bitness {
+                let mut section = read_section(
+                    name,
+                    section_hdr,
+                    section_bitness,
+                    data,
+                    ctx,
+                )?;
+
+                // Sections of relocatable objects all start at address
+                // 0, so they are laid out one after another.
+                //
+                if is_relocatable {
+                    section.address = sections
+                        .last()
+                        .map_or(Some(0), |x: &Section| {
+                            x.address.checked_add(x.data.len())
+                        })
+                        .and_then(|x| {
+                            x.checked_next_multiple_of(
+                                section_hdr.sh_addralign.max(1) as usize,
+                            )
+                        })
+                        .filter(|x| {
+                            x.checked_add(section.data.len()).is_some()
+                        })
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "The '{name}' section cannot be laid out \
+                                 after the previous sections"
+                            )
+                        })?;
+                }
 
-        if text_end >= data.len() {
+                addresses.insert(index, section.address);
+                sections.push(section);
+            }
+        }
+
+        if sections.is_empty() {
             return Err(anyhow!(
-                "Invalid offset + size: {} which is greater than the \
-                 binary size, {}",
-                text_end,
-                data.len()
+                "Binary does not contain a '.text' section"
             ));
         }
 
-        let text_begin_virtual = usize::try_from(text_hdr.sh_addr)
-            .with_context(|| {
-                format!(
-                    "The '.text' section has virtual address {} which \
-                 is greater than usize::MAX on this platform",
-                    text_hdr.sh_addr
-                )
-            })?;
+        // Names in `.dynsym` are looked up in `.dynstr` and qualified
+        // with their version from `.gnu.version` and `.gnu.version_d`,
+        // e.g., `memcpy@@GLIBC_2.14`.  Symbols in `.symtab` that refer
+        // to the same function are skipped.
+        //
+        let mut addrs = Vec::new();
+        let mut exported = HashSet::new();
+        let mut dynamic = HashSet::new();
 
-        let text_size = text_end - text_begin;
-        let text_end_virtual = text_begin_virtual + text_size;
+        for (index, sym) in elf.dynsyms.iter().enumerate() {
+            let Some(name) = elf.dynstrtab.get_at(sym.st_name) else {
+                continue;
+            };
+            let Some(addr) = symbol_address(name, &sym)? else {
+                continue;
+            };
+            let versioned_name = match symbol_version(elf, index) {
+                Some(version) => format!("{name}{version}"),
+                None => name.to_string(),
+            };
 
-        // Collect symbol addresses and names in increasing order.
-        //
+            if is_exported_function(&sym) {
+                exported.insert(versioned_name.clone());
+            }
 
-        let mut addrs = BinaryHeap::new();
+            dynamic.insert((addr, name));
+            addrs.push((addr, versioned_name));
+        }
 
-        for sym in elf.syms.iter().chain(elf.dynsyms.iter()) {
-            let Some(name) = elf
-                .strtab
-                .get_at(sym.st_name)
-                .or_else(|| elf.dynstrtab.get_at(sym.st_name))
-            else {
+        for sym in elf.syms.iter() {
+            let Some(name) = elf.strtab.get_at(sym.st_name) else {
                 continue;
             };
+            let addr = if is_relocatable {
+                // Values are relative to the symbol's section.
+                //
+                if matches!(
+                    sym.st_type(),
+                    elf::sym::STT_SECTION | elf::sym::STT_FILE
+                ) {
+                    continue;
+                }
 
-            let addr =
-                usize::try_from(sym.st_value).with_context(|| {
-                    format!(
-                    "Symbol '{}' has address {} which is greater than
-                     usize::MAX on this platform",
-                    name, sym.st_value
-                )
-                })?;
+                let Some(address) = addresses
+                    .get(&sym.st_shndx)
+                    .and_then(|x| x.checked_add(sym.st_value as usize))
+                else {
+                    continue;
+                };
 
-            if addr == 0
-                || addr < text_begin_virtual
-                || addr >= text_end_virtual
-            {
-                // addr == 0 if the symbol is defined outside of the
-                // binary.
-                //
-                // addr < text_begin_virtual or addr >= text_end_virtual
-                // if the symbol is defined outside of the `.text`
-                // section.
+                address
+            } else {
+                match symbol_address(name, &sym)? {
+                    Some(addr) => addr,
+                    None => continue,
+                }
+            };
+            let unversioned_name =
+                name.split_once('@').map_or(name, |(x, _)| x);
+
+            if dynamic.contains(&(addr, unversioned_name)) {
                 continue;
             }
 
-            addrs.push(Reverse((addr, name)));
+            addrs.push((addr, name.to_string()));
         }
 
-        let mut symbols = HashMap::new();
+        let mut symbols =
+            symbol_ranges(&sections, addrs, bitness_overrides);
+        let mut fpu_calls = HashSet::new();
 
-        // Extract instructions for each symbol.
-        //
+        if is_kernel {
+            let alternatives =
+                kernel::alternatives(elf, data, &addresses);
+
+            symbols.extend(alternative_ranges(
+                &sections,
+                &symbols,
+                &alternatives,
+            ));
+
+            if is_relocatable {
+                fpu_calls = kernel::fpu_calls(elf, &addresses);
+            }
+        }
+
+        Ok(Binary {
+            format: "elf",
+            sha256: String::new(),
+            bitness,
+            decoder_options,
+            sections,
+            symbols,
+            exported,
+            fpu_calls,
+        })
+    }
+
+    /// Reads the sections that contain code, and sections listed in
+    /// `bitness_overrides`.  Symbols are taken from the export table.
+    pub fn from_pe(
+        pe: &pe::PE,
+        data: &[u8],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        let bitness = match pe.header.coff_header.machine {
+            pe::header::COFF_MACHINE_X86 => 32,
+            pe::header::COFF_MACHINE_X86_64 => 64,
+            machine => {
+                return Err(anyhow!(
+                    "Unknown instruction set architecture: {machine}"
+                ))
+            }
+        };
+        let mut sections = Vec::new();
+
+        for section in pe.sections.iter() {
+            let name = section.name()?;
+            let section_bitness = bitness_overrides
+                .iter()
+                .find(|(x, _)| x == name)
+                .map(|&(_, bits)| bits)
+                .or_else(|| {
+                    (section.characteristics
+                        & pe::section_table::IMAGE_SCN_CNT_CODE
+                        != 0)
+                        .then_some(bitness)
+                });
+
+            let Some(section_bitness) = section_bitness else {
+                continue;
+            };
 
-        while let Some(Reverse((addr, name))) = addrs.pop() {
-            // Relative address within the `.text` section.
+            // The raw data is padded to the file alignment, so it can
+            // be longer than the section in memory.
             //
-            let begin = addr - text_begin_virtual;
-            let end = match addrs.peek() {
-                Some(Reverse((next, _))) => next - text_begin_virtual,
-                None => text_size,
+            let begin = section.pointer_to_raw_data as usize;
+            let size =
+                section.size_of_raw_data.min(section.virtual_size);
+            let code =
+                data.get(begin..begin + size as usize).ok_or_else(
+                    || anyhow!("The '{name}' section is out of bounds"),
+                )?;
+
+            sections.push(Section {
+                name: name.to_string(),
+                address: pe.image_base
+                    + section.virtual_address as usize,
+                bitness: section_bitness,
+                data: code.to_vec(),
+            });
+        }
+
+        if sections.is_empty() {
+            return Err(anyhow!("Binary does not contain any code"));
+        }
+
+        let mut addrs = Vec::new();
+        let mut exported = HashSet::new();
+
+        for export in pe.exports.iter() {
+            let Some(name) = export.name else {
+                continue;
             };
 
-            symbols.insert(name.to_owned(), (begin, end));
+            if export.reexport.is_some() {
+                continue;
+            }
+
+            exported.insert(name.to_string());
+            addrs.push((pe.image_base + export.rva, name.to_string()));
         }
 
+        let symbols =
+            symbol_ranges(&sections, addrs, bitness_overrides);
+
         Ok(Binary {
+            format: "pe",
+            sha256: String::new(),
             bitness,
-            text: data[text_begin..text_end].to_vec(),
+            decoder_options,
+            sections,
             symbols,
+            exported,
+            fpu_calls: HashSet::new(),
         })
     }
 
-    pub fn instruction_counts(
-        &self,
-    ) -> HashMap<(Mnemonic, &'static [CpuidFeature]), usize> {
-        let mut result = HashMap::new();
+    /// Reads the sections that contain instructions, e.g.,
+    /// `__TEXT,__text`, and sections listed in `bitness_overrides`.
+    pub fn from_mach(
+        macho: &mach::MachO,
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        let bitness = match macho.header.cputype {
+            CPU_TYPE_X86 => 32,
+            CPU_TYPE_X86_64 => 64,
+            cputype => {
+                return Err(anyhow!(
+                    "Unknown instruction set architecture: {cputype}"
+                ))
+            }
+        };
+        let mut sections = Vec::new();
 
-        for (mnemonic, features) in
-            instructions(&self.text, self.bitness)
-        {
-            result
-                .entry((mnemonic, features))
-                .and_modify(|counter| *counter += 1)
-                .or_insert(1);
+        for segment in macho.segments.iter() {
+            for (section, code) in segment.sections()? {
+                let name = format!(
+                    "{},{}",
+                    section.segname()?,
+                    section.name()?
+                );
+                let section_bitness = bitness_overrides
+                    .iter()
+                    .find(|(x, _)| *x == name)
+                    .map(|&(_, bits)| bits)
+                    .or_else(|| {
+                        (section.flags
+                            & (S_ATTR_PURE_INSTRUCTIONS
+                                | S_ATTR_SOME_INSTRUCTIONS)
+                            != 0)
+                            .then_some(bitness)
+                    });
+
+                if let Some(section_bitness) = section_bitness {
+                    sections.push(Section {
+                        name,
+                        address: usize::try_from(section.addr)?,
+                        bitness: section_bitness,
+                        data: code.to_vec(),
+                    });
+                }
+            }
         }
 
-        result
-    }
+        if sections.is_empty() {
+            return Err(anyhow!("Binary does not contain any code"));
+        }
 
-    pub fn instruction_counts_by_symbol(
-        &self,
-    ) -> anyhow::Result<
-        HashMap<(&str, Mnemonic, &'static [CpuidFeature]), usize>,
-    > {
-        anyhow::ensure!(
-            !self.symbols.is_empty(),
-            "No symbols found in the '.text' section, the binary may \
-             have been stripped"
-        );
+        let mut addrs = Vec::new();
+        let mut exported = HashSet::new();
 
-        let mut result = HashMap::new();
+        for symbol in macho.symbols() {
+            let (name, nlist) = symbol?;
 
-        for (name, &(begin, end)) in self.symbols.iter() {
-            for (mnemonic, features) in
-                instructions(&self.text[begin..end], self.bitness)
-            {
-                result
-                    .entry((name.as_str(), mnemonic, features))
-                    .and_modify(|counter| *counter += 1)
-                    .or_insert(1);
+            if nlist.is_stab() || nlist.get_type() != N_SECT {
+                continue;
+            }
+
+            if nlist.is_global() && nlist.n_type & N_PEXT == 0 {
+                exported.insert(name.to_string());
             }
+
+            addrs.push((
+                usize::try_from(nlist.n_value)?,
+                name.to_string(),
+            ));
         }
 
-        Ok(result)
-    }
-}
+        let symbols =
+            symbol_ranges(&sections, addrs, bitness_overrides);
 
-fn instructions(
-    data: &[u8],
-    bitness: u32,
-) -> Vec<(Mnemonic, &'static [CpuidFeature])> {
-    let mut decoder = Decoder::new(bitness, data, DecoderOptions::NONE);
-    let mut instruction = Instruction::default();
-    let mut result = Vec::new();
+        Ok(Binary {
+            format: "mach-o",
+            sha256: String::new(),
+            bitness,
+            decoder_options,
+            sections,
+            symbols,
+            exported,
+            fpu_calls: HashSet::new(),
+        })
+    }
 
-    while decoder.can_decode() {
-        decoder.decode_out(&mut instruction);
+    /// Treats `data` as a single region of code loaded at
+    /// `base_address`, e.g., a firmware blob or a JIT code dump.
+    pub fn from_raw(
+        data: &[u8],
+        base_address: usize,
+        bitness: u32,
+        symbols: &[(usize, String)],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        let sections = vec![Section {
+            name: "raw".to_string(),
+            address: base_address,
+            bitness,
+            data: data.to_vec(),
+        }];
+        let symbols = symbol_ranges(
+            &sections,
+            symbols.iter().cloned(),
+            bitness_overrides,
+        );
 
-        result.push((
-            instruction.op_code().mnemonic(),
-            instruction.cpuid_features(),
-        ));
+        Ok(Binary {
+            format: "raw",
+            sha256: sha256(data),
+            bitness,
+            decoder_options,
+            sections,
+            symbols,
+            exported: HashSet::new(),
+            fpu_calls: HashSet::new(),
+        })
     }
 
-    result
-}
+    /// Reads the functions in a jitdump file.  Each function is a
+    /// section of its own, since functions can be compiled again at the
+    /// same address.
+    pub fn from_jitdump(
+        data: &[u8],
+        decoder_options: u32,
+        bitness_overrides: &[(String, u32)],
+    ) -> anyhow::Result<Self> {
+        let dump = jit::read_jitdump(data)?;
+        let mut sections = Vec::new();
+        let mut symbols = Vec::new();
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        for function in dump.functions {
+            let bitness = bitness_overrides
+                .iter()
+                .find(|(x, _)| *x == function.name)
+                .map(|&(_, bits)| bits)
+                .unwrap_or(dump.bitness);
 
-    #[test]
-    fn instructions_sse() {
-        #[rustfmt::skip]
-        let add_arrays_sse: &[u8] = &[
-            0x0f, 0x28, 0x06,  // movaps xmm0,XMMWORD PTR [rsi]
-            0x0f, 0x28, 0x0a,  // movaps xmm1,XMMWORD PTR [rdx]
-            0x0f, 0x58, 0xc1,  // addps xmm0,xmm1
-            0x0f, 0x29, 0x07,  // movaps XMMWORD PTR [rdi],xmm0
-            0xc3,              // ret
-            0x0f, 0x1f, 0x00,  // nop DWORD PTR [rax]
-        ];
-        let result: Vec<(Mnemonic, &[CpuidFeature])> = vec![
-            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
-            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
-            (Mnemonic::Addps, &[CpuidFeature::SSE]),
-            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
-            (Mnemonic::Ret, &[CpuidFeature::X64]),
-            (Mnemonic::Nop, &[CpuidFeature::MULTIBYTENOP]),
-        ];
+            symbols.push((
+                function.name.clone(),
+                SymbolRange {
+                    section: sections.len(),
+                    begin: 0,
+                    end: function.code.len(),
+                    bitness,
+                },
+            ));
+            sections.push(Section {
+                name: function.name,
+                address: function.address,
+                bitness,
+                data: function.code,
+            });
+        }
+
+        if sections.is_empty() {
+            return Err(anyhow!(
+                "The jitdump file does not contain any code"
+            ));
+        }
 
-        assert_eq!(instructions(&add_arrays_sse, 64), result);
+        Ok(Binary {
+            format: "jitdump",
+            sha256: String::new(),
+            bitness: dump.bitness,
+            decoder_options,
+            sections,
+            symbols,
+            exported: HashSet::new(),
+            fpu_calls: HashSet::new(),
+        })
     }
 
-    #[test]
-    fn instructions_avx2() {
-        #[rustfmt::skip]
-        let add_arrays_avx2: &[u8] = &[
-            0xc5, 0xfc, 0x77,        // vzeroall
-            0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
-            0xc5, 0xfc, 0x28, 0x0a,  // vmovaps ymm1,YMMWORD PTR [rdx]
-            0xc5, 0xfc, 0x58, 0xd1,  // vaddps ymm2,ymm0,ymm1
-            0xc5, 0xfc, 0x29, 0x17,  // vmovaps YMMWORD PTR [rdi],ymm2
-            0xc3,                    // ret
-            0x66, 0x2e, 0x0f, 0x1f, 0x84, 0x00, 0x00, // cs nop WORD PTR [rax+rax*1+0x0]
-            0x00, 0x00, 0x00,
-            0x66, 0x90,              // xchg ax,ax
-        ];
-        let result: Vec<(Mnemonic, &[CpuidFeature])> = vec![
-            (Mnemonic::Vzeroall, &[CpuidFeature::AVX]),
-            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
-            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
-            (Mnemonic::Vaddps, &[CpuidFeature::AVX]),
-            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
-            (Mnemonic::Ret, &[CpuidFeature::X64]),
-            (Mnemonic::Nop, &[CpuidFeature::MULTIBYTENOP]),
-            (Mnemonic::Nop, &[CpuidFeature::INTEL8086]),
-        ];
+    /// Adds symbols with known sizes, e.g., from a perf map.  Symbols
+    /// outside of the code are ignored, and symbols that extend past
+    /// the end of a section are truncated.
+    pub fn add_symbols(&mut self, symbols: &[(usize, usize, String)]) {
+        for (addr, size, name) in symbols {
+            let Some(index) =
+                self.sections.iter().position(|section| {
+                    *addr >= section.address
+                        && *addr < section.address + section.data.len()
+                })
+            else {
+                continue;
+            };
+            let section = &self.sections[index];
+            let begin = addr - section.address;
 
-        assert_eq!(instructions(&add_arrays_avx2, 64), result);
+            self.symbols.push((
+                name.clone(),
+                SymbolRange {
+                    section: index,
+                    begin,
+                    end: begin
+                        .saturating_add(*size)
+                        .min(section.data.len()),
+                    bitness: section.bitness,
+                },
+            ));
+        }
     }
 
-    #[test]
-    fn instructions_avx512() {
-        #[rustfmt::skip]
-        let add_arrays_avx512: &[u8] = &[
-            0xc5, 0xfc, 0x77,                    // vzeroall
-            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x06,  // vmovaps zmm0,ZMMWORD PTR [rsi]
-            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x0a,  // vmovaps zmm1,ZMMWORD PTR [rdx]
-            0x62, 0xf1, 0x7c, 0x48, 0x58, 0xd1,  // vaddps zmm2,zmm0,zmm1
+    /// Keeps only the code at the addresses in `range`, and moves it so
+    /// that `range.start` is at `address`, e.g., to the address at which
+    /// a process maps part of the binary.
+    pub fn relocate(&mut self, range: Range<usize>, address: usize) {
+        let move_address = |x: usize| x - range.start + address;
+        // New index of each section, and the offset and size of the
+        // part that is kept.
+        //
+        let mut kept = Vec::new();
+        let mut sections = Vec::new();
+
+        for section in std::mem::take(&mut self.sections) {
+            let begin = section.address.max(range.start);
+            let end =
+                (section.address + section.data.len()).min(range.end);
+
+            if begin >= end {
+                kept.push(None);
+                continue;
+            }
+
+            let offset = begin - section.address;
+
+            kept.push(Some((sections.len(), offset, end - begin)));
+            sections.push(Section {
+                address: move_address(begin),
+                data: section.data[offset..offset + end - begin]
+                    .to_vec(),
+                ..section
+            });
+        }
+
+        self.sections = sections;
+        self.symbols = std::mem::take(&mut self.symbols)
+            .into_iter()
+            .filter_map(|(name, symbol)| {
+                let (section, offset, size) = kept[symbol.section]?;
+                let begin = symbol.begin.clamp(offset, offset + size);
+                let end = symbol.end.clamp(offset, offset + size);
+
+                (begin < end).then(|| {
+                    (
+                        name,
+                        SymbolRange {
+                            section,
+                            begin: begin - offset,
+                            end: end - offset,
+                            ..symbol
+                        },
+                    )
+                })
+            })
+            .collect();
+        self.fpu_calls = self
+            .fpu_calls
+            .iter()
+            .filter(|x| range.contains(x))
+            .map(|&x| move_address(x))
+            .collect();
+    }
+
+    /// Ranges of code in increasing order of address within each
+    /// section, along with the bitness used to decode them.
+    pub fn regions(&self) -> Vec<Region<'_>> {
+        let mut result = Vec::new();
+
+        for (index, section) in self.sections.iter().enumerate() {
+            let mut overridden: Vec<_> = self
+                .symbols
+                .iter()
+                .filter(|(_, range)| {
+                    range.section == index
+                        && range.bitness != section.bitness
+                        && range.begin < range.end
+                })
+                .collect();
+
+            overridden.sort_by_key(|(_, range)| range.begin);
+
+            let mut position = 0;
+
+            for (name, range) in overridden {
+                // Overlapping symbols are clipped to the code that the
+                // previous ones do not cover, so that no byte is
+                // decoded twice.
+                //
+                let begin = range.begin.max(position);
+
+                if begin >= range.end {
+                    continue;
+                }
+
+                if position < begin {
+                    result.push(Region {
+                        name: &section.name,
+                        address: section.address + position,
+                        bitness: section.bitness,
+                        code: &section.data[position..begin],
+                    });
+                }
+
+                result.push(Region {
+                    name,
+                    address: section.address + begin,
+                    bitness: range.bitness,
+                    code: &section.data[begin..range.end],
+                });
+
+                position = range.end;
+            }
+
+            if position < section.data.len() {
+                result.push(Region {
+                    name: &section.name,
+                    address: section.address + position,
+                    bitness: section.bitness,
+                    code: &section.data[position..],
+                });
+            }
+        }
+
+        result
+    }
+
+    /// Raw names and start addresses of the symbols.
+    pub fn symbol_starts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.symbols.iter().map(|(name, range)| {
+            (
+                name.as_str(),
+                self.sections[range.section].address + range.begin,
+            )
+        })
+    }
+
+    /// Whether `address` is in the code of the binary.
+    pub fn contains(&self, address: usize) -> bool {
+        self.sections.iter().any(|section| {
+            (section.address..section.address + section.data.len())
+                .contains(&address)
+        })
+    }
+
+    /// Whether `name` is a function that is exported in `.dynsym` and
+    /// can thus be called by other binaries.
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name)
+    }
+
+    pub fn format(&self) -> &'static str {
+        self.format
+    }
+
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// Bitness implied by the binary's architecture.
+    pub fn bitness(&self) -> u32 {
+        self.bitness
+    }
+
+    /// Whether any code is decoded with a bitness other than the one
+    /// implied by the binary's architecture.
+    pub fn has_mixed_bitness(&self) -> bool {
+        self.sections
+            .iter()
+            .any(|section| section.bitness != self.bitness)
+            || self
+                .symbols
+                .iter()
+                .any(|(_, range)| range.bitness != self.bitness)
+    }
+
+    fn instructions(&self) -> Vec<DecodedInstruction> {
+        self.regions()
+            .into_iter()
+            .flat_map(|region| {
+                instructions(
+                    region.code,
+                    region.address,
+                    region.bitness,
+                    self.decoder_options,
+                )
+            })
+            .collect()
+    }
+
+    fn symbol_instructions(
+        &self,
+        range: &SymbolRange,
+    ) -> Vec<DecodedInstruction> {
+        let section = &self.sections[range.section];
+
+        instructions(
+            &section.data[range.begin..range.end],
+            section.address + range.begin,
+            range.bitness,
+            self.decoder_options,
+        )
+    }
+
+    /// Names of the decoder options that are enabled, as listed in
+    /// `DECODER_OPTIONS`.
+    pub fn decoder_option_names(&self) -> Vec<&'static str> {
+        DECODER_OPTIONS
+            .iter()
+            .filter(|&&(_, bit)| self.decoder_options & bit != 0)
+            .map(|&(name, _)| name)
+            .collect()
+    }
+
+    /// Counts instructions or, if `samples` are given, the samples at
+    /// the addresses of instructions.  The length of an instruction is
+    /// counted once, even if it was sampled several times.
+    pub fn instruction_counts(
+        &self,
+        by_encoding: bool,
+        samples: Option<&BTreeMap<usize, usize>>,
+    ) -> Counts {
+        let mut result = HashMap::new();
+
+        for instruction in self.instructions() {
+            let weight = weight(&instruction, samples);
+
+            if weight == 0 {
+                continue;
+            }
+
+            let encoding = by_encoding.then_some(instruction.encoding);
+
+            *result
+                .entry((
+                    instruction.mnemonic,
+                    instruction.features,
+                    encoding,
+                ))
+                .or_default() += Usage {
+                count: weight,
+                bytes: instruction.length,
+            };
+        }
+
+        result
+    }
+
+    pub fn vector_usage(&self) -> VectorUsage {
+        let mut result = VectorUsage::default();
+
+        for instruction in self.instructions() {
+            result.add(&instruction);
+        }
+
+        result
+    }
+
+    pub fn vector_usage_by_symbol(
+        &self,
+    ) -> anyhow::Result<HashMap<&str, VectorUsage>> {
+        anyhow::ensure!(
+            !self.symbols.is_empty(),
+            "No symbols found in the '.text' section, the binary may \
+             have been stripped"
+        );
+
+        let mut result = HashMap::new();
+
+        for (name, range) in self.symbols.iter() {
+            let usage: &mut VectorUsage =
+                result.entry(name.as_str()).or_default();
+
+            for instruction in self.symbol_instructions(range) {
+                usage.add(&instruction);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Same as `instruction_counts`, but by symbol.
+    pub fn instruction_counts_by_symbol(
+        &self,
+        by_encoding: bool,
+        samples: Option<&BTreeMap<usize, usize>>,
+    ) -> anyhow::Result<SymbolCounts<'_>> {
+        anyhow::ensure!(
+            !self.symbols.is_empty(),
+            "No symbols found in the '.text' section, the binary may \
+             have been stripped"
+        );
+
+        let mut result = HashMap::new();
+
+        for (name, range) in self.symbols.iter() {
+            for instruction in self.symbol_instructions(range) {
+                let weight = weight(&instruction, samples);
+
+                if weight == 0 {
+                    continue;
+                }
+
+                let encoding =
+                    by_encoding.then_some(instruction.encoding);
+
+                *result
+                    .entry((
+                        name.as_str(),
+                        instruction.mnemonic,
+                        instruction.features,
+                        encoding,
+                    ))
+                    .or_default() += Usage {
+                    count: weight,
+                    bytes: instruction.length,
+                };
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Number of SIMD instructions in each function that does not call
+    /// `kernel_fpu_begin`, counting the alternatives that can be
+    /// patched into the function.  Kernel code must not touch the FPU
+    /// or vector registers outside of `kernel_fpu_begin` and
+    /// `kernel_fpu_end`.
+    ///
+    /// Helpers that are only called with the FPU already enabled are
+    /// reported as well, since callers are not tracked.
+    pub fn unguarded_simd(
+        &self,
+    ) -> anyhow::Result<HashMap<&str, usize>> {
+        anyhow::ensure!(
+            !self.symbols.is_empty(),
+            "No symbols found in the '.text' section, the binary may \
+             have been stripped"
+        );
+
+        let fpu_begin: HashSet<usize> = self
+            .symbols
+            .iter()
+            .filter(|(name, _)| {
+                kernel::FPU_BEGIN.contains(&name.as_str())
+            })
+            .map(|(_, range)| {
+                self.sections[range.section].address + range.begin
+            })
+            .collect();
+        // Number of SIMD instructions and whether `kernel_fpu_begin` is
+        // called, by function.
+        //
+        let mut functions: HashMap<&str, (usize, bool)> =
+            HashMap::new();
+
+        for (name, range) in self.symbols.iter() {
+            let section = &self.sections[range.section];
+            let name = name
+                .strip_suffix(kernel::ALTERNATIVE_SUFFIX)
+                .unwrap_or(name);
+            let (simd, calls_fpu_begin) =
+                functions.entry(name).or_default();
+            let mut decoder = Decoder::with_ip(
+                range.bitness,
+                &section.data[range.begin..range.end],
+                (section.address + range.begin) as u64,
+                self.decoder_options,
+            );
+
+            for instruction in decoder.iter() {
+                if is_simd(&instruction) {
+                    *simd += 1;
+                }
+
+                // In modules, the operand of the call is relocated.
+                //
+                if instruction.is_call_near()
+                    && (fpu_begin.contains(
+                        &(instruction.near_branch_target() as usize),
+                    ) || self.fpu_calls.contains(
+                        &((instruction.next_ip() as usize)
+                            .saturating_sub(4)),
+                    ))
+                {
+                    *calls_fpu_begin = true;
+                }
+            }
+        }
+
+        Ok(functions
+            .into_iter()
+            .filter(|&(_, (simd, calls_fpu_begin))| {
+                simd > 0 && !calls_fpu_begin
+            })
+            .map(|(name, (simd, _))| (name, simd))
+            .collect())
+    }
+}
+
+/// Assigns each symbol the range of code from its address to the
+/// address of the next symbol in the same section, or to the end of the
+/// section.  Symbols outside of `sections` are ignored.
+fn symbol_ranges(
+    sections: &[Section],
+    addrs: impl IntoIterator<Item = (usize, String)>,
+    bitness_overrides: &[(String, u32)],
+) -> Vec<(String, SymbolRange)> {
+    // Collect symbol addresses and names in increasing order.
+    //
+
+    let mut heap = BinaryHeap::new();
+
+    for (addr, name) in addrs {
+        let Some(section) = sections.iter().position(|section| {
+            addr >= section.address
+                && addr < section.address + section.data.len()
+        }) else {
+            // The symbol is defined outside of the sections that
+            // contain code.
+            continue;
+        };
+
+        heap.push(Reverse((section, addr, name)));
+    }
+
+    let mut symbols = Vec::new();
+
+    while let Some(Reverse((section, addr, name))) = heap.pop() {
+        let section_address = sections[section].address;
+
+        // Relative address within the section.
+        //
+        let begin = addr - section_address;
+        let end = match heap.peek() {
+            Some(Reverse((next_section, next, _)))
+                if *next_section == section =>
+            {
+                next - section_address
+            }
+            _ => sections[section].data.len(),
+        };
+        let bitness = bitness_overrides
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|&(_, bits)| bits)
+            .unwrap_or(sections[section].bitness);
+
+        symbols.push((
+            name,
+            SymbolRange {
+                section,
+                begin,
+                end,
+                bitness,
+            },
+        ));
+    }
+
+    symbols
+}
+
+/// Assigns the replacement instructions of each alternative to the
+/// function that they are patched into.  Alternatives that are not
+/// patched into a function are skipped.
+fn alternative_ranges(
+    sections: &[Section],
+    symbols: &[(String, SymbolRange)],
+    alternatives: &[kernel::Alternative],
+) -> Vec<(String, SymbolRange)> {
+    let find = |addr: usize| {
+        sections.iter().position(|section| {
+            addr >= section.address
+                && addr < section.address + section.data.len()
+        })
+    };
+    // The kernel has tens of thousands of both, so the functions are
+    // looked up by binary search.
+    //
+    let mut functions: Vec<_> = symbols
+        .iter()
+        .map(|(name, range)| {
+            (range.section, range.begin, range.end, name)
+        })
+        .collect();
+    let mut result = Vec::new();
+
+    functions.sort();
+
+    for alternative in alternatives {
+        let (Some(site_section), Some(section)) =
+            (find(alternative.site), find(alternative.replacement))
+        else {
+            continue;
+        };
+        let site = alternative.site - sections[site_section].address;
+        let index = functions.partition_point(|&(x, begin, ..)| {
+            (x, begin) <= (site_section, site)
+        });
+        let Some(&(.., name)) = index
+            .checked_sub(1)
+            .map(|index| &functions[index])
+            .filter(|&&(x, _, end, _)| x == site_section && site < end)
+        else {
+            continue;
+        };
+        let begin = alternative.replacement - sections[section].address;
+        let end = (begin + alternative.length)
+            .min(sections[section].data.len());
+
+        if begin < end {
+            result.push((
+                format!("{name}{}", kernel::ALTERNATIVE_SUFFIX),
+                SymbolRange {
+                    section,
+                    begin,
+                    end,
+                    bitness: sections[section].bitness,
+                },
+            ));
+        }
+    }
+
+    result
+}
+
+/// Number of bytes read by `read_header`.
+const HEADER_SIZE: usize = 512;
+
+/// Reads the first bytes of `path`, which are enough to identify ELF
+/// files and the other supported file formats.  Tar archives need the
+/// most: their magic number follows the 257-byte file name.
+pub fn read_header(path: &Path) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    let file = std::fs::File::open(path).ok()?;
+
+    file.take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+
+    Some(header)
+}
+
+/// Returns the architecture from an ELF header, or `None` if `header`
+/// does not belong to an ELF file.
+pub fn elf_machine(header: &[u8]) -> Option<u16> {
+    elf::Elf::parse_header(header)
+        .ok()
+        .map(|header| header.e_machine)
+}
+
+/// Whether `data` is an ELF, PE, or Mach-O binary that contains x86
+/// code.
+pub fn is_x86_object(data: &[u8]) -> bool {
+    match Object::parse(data) {
+        Ok(Object::Elf(elf)) => {
+            matches!(
+                elf.header.e_machine,
+                elf::header::EM_386 | elf::header::EM_X86_64
+            )
+        }
+        Ok(Object::PE(pe)) => matches!(
+            pe.header.coff_header.machine,
+            pe::header::COFF_MACHINE_X86
+                | pe::header::COFF_MACHINE_X86_64
+        ),
+        Ok(Object::Mach(mach::Mach::Binary(macho))) => matches!(
+            macho.header.cputype,
+            CPU_TYPE_X86 | CPU_TYPE_X86_64
+        ),
+        Ok(Object::Mach(mach::Mach::Fat(multi))) => {
+            multi.iter_arches().any(|arch| {
+                arch.is_ok_and(|x| {
+                    matches!(x.cputype, CPU_TYPE_X86 | CPU_TYPE_X86_64)
+                })
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Whether `header` starts like a PE or Mach-O file.  Unlike ELF
+/// files, their architecture can only be checked with
+/// `is_x86_object` on the whole file.
+pub fn is_pe_or_mach(header: &[u8]) -> bool {
+    header
+        .get(..16)
+        .and_then(|x| goblin::peek_bytes(x.try_into().ok()?).ok())
+        .is_some_and(|hint| {
+            matches!(
+                hint,
+                goblin::Hint::PE
+                    | goblin::Hint::Mach(_)
+                    | goblin::Hint::MachFat(_)
+            )
+        })
+}
+
+/// Whether `data` starts with the header of an x86 ELF file.
+pub fn is_x86_elf(data: &[u8]) -> bool {
+    matches!(
+        elf_machine(data),
+        Some(elf::header::EM_386 | elf::header::EM_X86_64)
+    )
+}
+
+/// Returns the address of `sym`, or `None` if the symbol is defined
+/// outside of the binary.
+fn symbol_address(
+    name: &str,
+    sym: &elf::Sym,
+) -> anyhow::Result<Option<usize>> {
+    let addr = usize::try_from(sym.st_value).with_context(|| {
+        format!(
+            "Symbol '{}' has address {} which is greater than
+                     usize::MAX on this platform",
+            name, sym.st_value
+        )
+    })?;
+
+    // addr == 0 if the symbol is defined outside of the binary.
+    //
+    Ok((addr != 0).then_some(addr))
+}
+
+/// Version suffix of the dynamic symbol at `index`, e.g.,
+/// `@@GLIBC_2.14` for the default version or `@GLIBC_2.2.5` for a
+/// hidden one.
+fn symbol_version(elf: &elf::Elf, index: usize) -> Option<String> {
+    let versym = elf.versym.as_ref()?.get_at(index)?;
+
+    if versym.is_local() || versym.is_global() {
+        return None;
+    }
+
+    let verdef = elf
+        .verdef
+        .as_ref()?
+        .iter()
+        .find(|verdef| verdef.vd_ndx == versym.version())?;
+    let verdaux = verdef.iter().next()?;
+    let version = elf.dynstrtab.get_at(verdaux.vda_name)?;
+
+    if versym.is_hidden() {
+        Some(format!("@{version}"))
+    } else {
+        Some(format!("@@{version}"))
+    }
+}
+
+/// Whether `sym` is a function with global or weak binding and default
+/// or protected visibility, i.e., whether other binaries can call it.
+fn is_exported_function(sym: &elf::Sym) -> bool {
+    use elf::sym::{
+        STB_GLOBAL, STB_WEAK, STT_FUNC, STT_GNU_IFUNC, STV_DEFAULT,
+        STV_PROTECTED,
+    };
+
+    sym.st_shndx != 0
+        && matches!(sym.st_bind(), STB_GLOBAL | STB_WEAK)
+        && matches!(sym.st_type(), STT_FUNC | STT_GNU_IFUNC)
+        && matches!(sym.st_visibility(), STV_DEFAULT | STV_PROTECTED)
+}
+
+/// SHA-256 digest of `data` in hexadecimal.
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Returns `length` bytes of `data` starting at `offset`, or everything
+/// after `offset` if `length` is `None`.
+pub fn raw_slice(
+    data: &[u8],
+    offset: usize,
+    length: Option<usize>,
+) -> anyhow::Result<&[u8]> {
+    let end = match length {
+        Some(length) => {
+            offset.checked_add(length).ok_or_else(|| {
+                anyhow!("Invalid offset + length: {offset} + {length}")
+            })?
+        }
+        None => data.len(),
+    };
+
+    data.get(offset..end).ok_or_else(|| {
+        anyhow!(
+            "Invalid offset and length: {offset}..{end} is outside of the \
+             input of size {}",
+            data.len()
+        )
+    })
+}
+
+/// Parses a symbol map with one symbol per line, either as `ADDRESS
+/// NAME` or in the `ADDRESS TYPE NAME` format printed by nm(1).
+/// Addresses are hexadecimal, with or without a `0x` prefix.  Lines
+/// without an address, such as undefined symbols in nm(1) output, are
+/// skipped.
+pub fn parse_symbol_map(text: &str) -> Vec<(usize, String)> {
+    let mut result = Vec::new();
+
+    for line in text.lines() {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (addr, name) = match fields.as_slice() {
+            [addr, name] => (addr, name),
+            [addr, kind, name, ..] if kind.len() == 1 => (addr, name),
+            _ => continue,
+        };
+        let addr = addr.strip_prefix("0x").unwrap_or(addr);
+
+        if let Ok(addr) = usize::from_str_radix(addr, 16) {
+            result.push((addr, name.to_string()));
+        }
+    }
+
+    result
+}
+
+/// Reads a section as code of the given bitness.
+fn read_section(
+    name: &str,
+    section_hdr: &elf::SectionHeader,
+    bitness: u32,
+    data: &[u8],
+    ctx: Ctx,
+) -> anyhow::Result<Section> {
+    let address =
+        usize::try_from(section_hdr.sh_addr).with_context(|| {
+            format!(
+                "The '{name}' section has virtual address {} which is \
+             greater than usize::MAX on this platform",
+                section_hdr.sh_addr
+            )
+        })?;
+    let contents = section_contents(name, section_hdr, data, ctx)?;
+
+    if address.checked_add(contents.len()).is_none() {
+        return Err(anyhow!(
+            "The '{name}' section at {address:#x} extends past the end \
+             of the address space"
+        ));
+    }
+
+    Ok(Section {
+        name: name.to_owned(),
+        address,
+        bitness,
+        data: contents,
+    })
+}
+
+/// Reads the contents of a section, decompressing it if it has the
+/// `SHF_COMPRESSED` flag.
+fn section_contents(
+    name: &str,
+    section_hdr: &elf::SectionHeader,
+    data: &[u8],
+    ctx: Ctx,
+) -> anyhow::Result<Vec<u8>> {
+    let begin =
+        usize::try_from(section_hdr.sh_offset).with_context(|| {
+            format!(
+            "The '{name}' section has offset {} which is greater than \
+             usize::MAX on this platform",
+            section_hdr.sh_offset
+        )
+        })?;
+
+    let size =
+        usize::try_from(section_hdr.sh_size).with_context(|| {
+            format!(
+            "The '{name}' section has size {} which is greater than \
+             usize::MAX on this platform",
+            section_hdr.sh_size
+        )
+        })?;
+    let end = begin.checked_add(size).with_context(|| {
+        format!(
+            "The '{name}' section has offset {begin} and size {size} \
+             whose sum is greater than usize::MAX"
+        )
+    })?;
+
+    if end > data.len() {
+        return Err(anyhow!(
+            "Invalid offset + size: {} which is greater than the \
+             binary size, {}",
+            end,
+            data.len()
+        ));
+    }
+
+    if !is_compressed(section_hdr) {
+        return Ok(data[begin..end].to_vec());
+    }
+
+    let chdr = CompressionHeader::parse(data, begin, ctx)
+        .with_context(|| {
+            format!("The '{name}' section has a malformed header")
+        })?;
+
+    compression::decompress_section(
+        chdr.ch_type,
+        data.get(begin + CompressionHeader::size(ctx)..end)
+            .unwrap_or_default(),
+    )
+    .with_context(|| {
+        format!("Could not decompress the '{name}' section")
+    })
+}
+
+fn is_compressed(section_hdr: &elf::SectionHeader) -> bool {
+    section_hdr.sh_flags
+        & u64::from(elf::section_header::SHF_COMPRESSED)
+        != 0
+}
+
+/// Copy of the ELF file `data` in which the sections that have the
+/// `SHF_COMPRESSED` flag and are not loaded into memory, e.g., DWARF
+/// sections or `.symtab` and `.strtab` in a separate debug file, are
+/// decompressed and moved to the end.  Returns `None` if there are no
+/// such sections.  `goblin` parses the symbol table as it is stored in
+/// the file, so it has to be decompressed beforehand.
+fn decompress_elf_sections(
+    data: &[u8],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let header = elf::Elf::parse_header(data)?;
+    let ctx = Ctx::new(header.container()?, header.endianness()?);
+    let section_headers = elf::SectionHeader::parse(
+        data,
+        header.e_shoff as usize,
+        header.e_shnum as usize,
+        ctx,
+    )?;
+    let mut result: Option<Vec<u8>> = None;
+
+    for (index, section_hdr) in section_headers.iter().enumerate() {
+        if !is_compressed(section_hdr) || section_hdr.is_alloc() {
+            continue;
+        }
+
+        let contents = section_contents(
+            &format!("#{index}"),
+            section_hdr,
+            data,
+            ctx,
+        )?;
+        let result = result.get_or_insert_with(|| data.to_vec());
+        let mut decompressed = section_hdr.clone();
+
+        decompressed.sh_offset = result.len() as u64;
+        decompressed.sh_size = contents.len() as u64;
+        decompressed.sh_flags &=
+            !u64::from(elf::section_header::SHF_COMPRESSED);
+        result.extend(contents);
+
+        let offset = header.e_shoff as usize
+            + index * header.e_shentsize as usize;
+
+        result.pwrite_with(decompressed, offset, ctx)?;
+    }
+
+    Ok(result)
+}
+
+/// Bitness implied by a section name such as `.text16` or `.code32`.
+fn bitness_from_section_name(name: &str) -> Option<u32> {
+    name.split('.').find_map(|component| match component {
+        "text16" | "code16" => Some(16),
+        "text32" | "code32" => Some(32),
+        "text64" | "code64" => Some(64),
+        _ => None,
+    })
+}
+
+fn instructions(
+    data: &[u8],
+    address: usize,
+    bitness: u32,
+    decoder_options: u32,
+) -> Vec<DecodedInstruction> {
+    let mut decoder = Decoder::with_ip(
+        bitness,
+        data,
+        address as u64,
+        decoder_options,
+    );
+    let mut instruction = Instruction::default();
+    let mut result = Vec::new();
+
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+
+        result.push(DecodedInstruction {
+            address: instruction.ip() as usize,
+            length: instruction.len(),
+            mnemonic: instruction.op_code().mnemonic(),
+            features: instruction.cpuid_features(),
+            encoding: instruction.encoding(),
+            vector_width: vector_width(&instruction),
+            masking: instruction.has_op_mask(),
+            zeroing_masking: instruction.zeroing_masking(),
+            broadcast: instruction.is_broadcast(),
+            rounding: instruction.rounding_control()
+                != RoundingControl::None
+                || instruction.suppress_all_exceptions(),
+        });
+    }
+
+    result
+}
+
+/// Number of times `instruction` is counted: once, or as many times as
+/// it was sampled.
+fn weight(
+    instruction: &DecodedInstruction,
+    samples: Option<&BTreeMap<usize, usize>>,
+) -> usize {
+    samples.map_or(1, |x| {
+        x.get(&instruction.address).copied().unwrap_or_default()
+    })
+}
+
+/// Whether `instruction` uses MMX or vector registers, or operates on
+/// packed data in memory.
+fn is_simd(instruction: &Instruction) -> bool {
+    vector_width(instruction).is_some()
+        || (0..instruction.op_count()).any(|index| {
+            instruction.op_kind(index) == OpKind::Register
+                && instruction.op_register(index).is_mm()
+        })
+}
+
+fn vector_width(instruction: &Instruction) -> Option<u32> {
+    let register_width = (0..instruction.op_count())
+        .filter(|&index| instruction.op_kind(index) == OpKind::Register)
+        .filter_map(|index| {
+            let register = instruction.op_register(index);
+
+            if register.is_zmm() {
+                Some(512)
+            } else if register.is_ymm() {
+                Some(256)
+            } else if register.is_xmm() {
+                Some(128)
+            } else {
+                None
+            }
+        })
+        .max();
+
+    register_width.or_else(|| {
+        let memory_size = instruction.memory_size();
+
+        if !memory_size.is_packed() {
+            return None;
+        }
+
+        match memory_size.size() {
+            16 => Some(128),
+            32 => Some(256),
+            64 => Some(512),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    fn mnemonics_and_features(
+        data: &[u8],
+        decoder_options: u32,
+    ) -> Vec<(Mnemonic, &'static [CpuidFeature])> {
+        instructions(data, 0, 64, decoder_options)
+            .into_iter()
+            .map(|x| (x.mnemonic, x.features))
+            .collect()
+    }
+
+    #[test]
+    fn instructions_sse() {
+        #[rustfmt::skip]
+        let add_arrays_sse: &[u8] = &[
+            0x0f, 0x28, 0x06,  // movaps xmm0,XMMWORD PTR [rsi]
+            0x0f, 0x28, 0x0a,  // movaps xmm1,XMMWORD PTR [rdx]
+            0x0f, 0x58, 0xc1,  // addps xmm0,xmm1
+            0x0f, 0x29, 0x07,  // movaps XMMWORD PTR [rdi],xmm0
+            0xc3,              // ret
+            0x0f, 0x1f, 0x00,  // nop DWORD PTR [rax]
+        ];
+        let result: Vec<(Mnemonic, &[CpuidFeature])> = vec![
+            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
+            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
+            (Mnemonic::Addps, &[CpuidFeature::SSE]),
+            (Mnemonic::Movaps, &[CpuidFeature::SSE]),
+            (Mnemonic::Ret, &[CpuidFeature::X64]),
+            (Mnemonic::Nop, &[CpuidFeature::MULTIBYTENOP]),
+        ];
+
+        assert_eq!(
+            mnemonics_and_features(
+                add_arrays_sse,
+                DecoderOptions::NONE
+            ),
+            result
+        );
+    }
+
+    #[test]
+    fn instructions_avx2() {
+        #[rustfmt::skip]
+        let add_arrays_avx2: &[u8] = &[
+            0xc5, 0xfc, 0x77,        // vzeroall
+            0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
+            0xc5, 0xfc, 0x28, 0x0a,  // vmovaps ymm1,YMMWORD PTR [rdx]
+            0xc5, 0xfc, 0x58, 0xd1,  // vaddps ymm2,ymm0,ymm1
+            0xc5, 0xfc, 0x29, 0x17,  // vmovaps YMMWORD PTR [rdi],ymm2
+            0xc3,                    // ret
+            0x66, 0x2e, 0x0f, 0x1f, 0x84, 0x00, 0x00, // cs nop WORD PTR [rax+rax*1+0x0]
+            0x00, 0x00, 0x00,
+            0x66, 0x90,              // xchg ax,ax
+        ];
+        let result: Vec<(Mnemonic, &[CpuidFeature])> = vec![
+            (Mnemonic::Vzeroall, &[CpuidFeature::AVX]),
+            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
+            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
+            (Mnemonic::Vaddps, &[CpuidFeature::AVX]),
+            (Mnemonic::Vmovaps, &[CpuidFeature::AVX]),
+            (Mnemonic::Ret, &[CpuidFeature::X64]),
+            (Mnemonic::Nop, &[CpuidFeature::MULTIBYTENOP]),
+            (Mnemonic::Nop, &[CpuidFeature::INTEL8086]),
+        ];
+
+        assert_eq!(
+            mnemonics_and_features(
+                add_arrays_avx2,
+                DecoderOptions::NONE
+            ),
+            result
+        );
+    }
+
+    #[test]
+    fn instructions_avx512() {
+        #[rustfmt::skip]
+        let add_arrays_avx512: &[u8] = &[
+            0xc5, 0xfc, 0x77,                    // vzeroall
+            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x06,  // vmovaps zmm0,ZMMWORD PTR [rsi]
+            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x0a,  // vmovaps zmm1,ZMMWORD PTR [rdx]
+            0x62, 0xf1, 0x7c, 0x48, 0x58, 0xd1,  // vaddps zmm2,zmm0,zmm1
             0x62, 0xf1, 0x7c, 0x48, 0x29, 0x17,  // vmovaps ZMMWORD PTR [rdi],zmm2
             0xc3,                                // ret
         ];
@@ -298,6 +1903,905 @@ mod test {
             (Mnemonic::Ret, &[CpuidFeature::X64]),
         ];
 
-        assert_eq!(instructions(&add_arrays_avx512, 64), result);
+        assert_eq!(
+            mnemonics_and_features(
+                add_arrays_avx512,
+                DecoderOptions::NONE
+            ),
+            result
+        );
+    }
+
+    #[test]
+    fn instructions_mpx() {
+        #[rustfmt::skip]
+        let bndmov: &[u8] = &[
+            0x66, 0x0f, 0x1a, 0xc1,  // bndmov bnd0,bnd1
+        ];
+
+        assert_eq!(
+            mnemonics_and_features(bndmov, DecoderOptions::NONE),
+            vec![(
+                Mnemonic::Reservednop,
+                &[CpuidFeature::MULTIBYTENOP][..]
+            )]
+        );
+        assert_eq!(
+            mnemonics_and_features(bndmov, DecoderOptions::MPX),
+            vec![(Mnemonic::Bndmov, &[CpuidFeature::MPX][..])]
+        );
+    }
+
+    #[test]
+    fn instructions_encoding() {
+        #[rustfmt::skip]
+        let mixed_encodings: &[u8] = &[
+            0x0f, 0x28, 0x06,                    // movaps xmm0,XMMWORD PTR [rsi]
+            0xc5, 0xfc, 0x28, 0x06,              // vmovaps ymm0,YMMWORD PTR [rsi]
+            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x06,  // vmovaps zmm0,ZMMWORD PTR [rsi]
+        ];
+        let result: Vec<_> =
+            instructions(mixed_encodings, 0, 64, DecoderOptions::NONE)
+                .into_iter()
+                .map(|x| (x.mnemonic, x.encoding))
+                .collect();
+
+        assert_eq!(
+            result,
+            vec![
+                (Mnemonic::Movaps, EncodingKind::Legacy),
+                (Mnemonic::Vmovaps, EncodingKind::VEX),
+                (Mnemonic::Vmovaps, EncodingKind::EVEX),
+            ]
+        );
+    }
+
+    #[test]
+    fn instructions_vector_width() {
+        #[rustfmt::skip]
+        let mixed_widths: &[u8] = &[
+            0x0f, 0x28, 0x06,                    // movaps xmm0,XMMWORD PTR [rsi]
+            0xc5, 0xfc, 0x28, 0x06,              // vmovaps ymm0,YMMWORD PTR [rsi]
+            0x62, 0xf1, 0x7c, 0x48, 0x28, 0x06,  // vmovaps zmm0,ZMMWORD PTR [rsi]
+            0xc5, 0xfc, 0x77,                    // vzeroall
+            0xc3,                                // ret
+        ];
+        let result: Vec<_> =
+            instructions(mixed_widths, 0, 64, DecoderOptions::NONE)
+                .into_iter()
+                .map(|x| (x.mnemonic, x.vector_width))
+                .collect();
+
+        assert_eq!(
+            result,
+            vec![
+                (Mnemonic::Movaps, Some(128)),
+                (Mnemonic::Vmovaps, Some(256)),
+                (Mnemonic::Vmovaps, Some(512)),
+                (Mnemonic::Vzeroall, None),
+                (Mnemonic::Ret, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn instructions_evex_facilities() {
+        #[rustfmt::skip]
+        let evex_facilities: &[u8] = &[
+            0x62, 0xf1, 0x7c, 0xc9, 0x58, 0xd1,  // vaddps zmm2{k1}{z},zmm0,zmm1
+            0x62, 0xf1, 0x7c, 0x58, 0x58, 0x16,  // vaddps zmm2,zmm0,DWORD PTR [rsi]{1to16}
+            0x62, 0xf1, 0x7c, 0x18, 0x58, 0xd1,  // vaddps zmm2,zmm0,zmm1,{rn-sae}
+            0x62, 0xf1, 0x7c, 0x48, 0x58, 0xd1,  // vaddps zmm2,zmm0,zmm1
+        ];
+        let result: Vec<_> =
+            instructions(evex_facilities, 0, 64, DecoderOptions::NONE)
+                .into_iter()
+                .map(|x| {
+                    (
+                        x.masking,
+                        x.zeroing_masking,
+                        x.broadcast,
+                        x.rounding,
+                    )
+                })
+                .collect();
+
+        assert_eq!(
+            result,
+            vec![
+                (true, true, false, false),
+                (false, false, true, false),
+                (false, false, false, true),
+                (false, false, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn instructions_16_bit() {
+        #[rustfmt::skip]
+        let real_mode: &[u8] = &[
+            0xb8, 0x34, 0x12,  // mov ax,0x1234
+            0xcd, 0x10,        // int 0x10
+        ];
+        let result: Vec<_> =
+            instructions(real_mode, 0, 16, DecoderOptions::NONE)
+                .into_iter()
+                .map(|x| x.mnemonic)
+                .collect();
+
+        assert_eq!(result, vec![Mnemonic::Mov, Mnemonic::Int]);
+    }
+
+    #[test]
+    fn section_name_bitness() {
+        assert_eq!(bitness_from_section_name(".text16"), Some(16));
+        assert_eq!(bitness_from_section_name(".code32"), Some(32));
+        assert_eq!(bitness_from_section_name(".init.text32"), Some(32));
+        assert_eq!(bitness_from_section_name(".text"), None);
+        assert_eq!(bitness_from_section_name(".text.unlikely"), None);
+    }
+
+    #[test]
+    fn symbol_map() {
+        let text = "\
+0x1000 entry
+0000000000001139 T main
+                 U puts
+0000000000001150 t helper extra
+not_an_address foo
+";
+
+        assert_eq!(
+            parse_symbol_map(text),
+            vec![
+                (0x1000, "entry".to_string()),
+                (0x1139, "main".to_string()),
+                (0x1150, "helper".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_symbols() {
+        #[rustfmt::skip]
+        let code: &[u8] = &[
+            0x0f, 0x28, 0x06,        // movaps xmm0,XMMWORD PTR [rsi]
+            0xc3,                    // ret
+            0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
+            0xc3,                    // ret
+        ];
+        let symbols = vec![
+            (0x400, "sse".to_string()),
+            (0x404, "avx".to_string()),
+        ];
+        let binary = Binary::from_raw(
+            code,
+            0x400,
+            64,
+            &symbols,
+            DecoderOptions::NONE,
+            &[],
+        )
+        .unwrap();
+        let counts =
+            binary.instruction_counts_by_symbol(false, None).unwrap();
+
+        assert_eq!(
+            counts.get(&(
+                "avx",
+                Mnemonic::Vmovaps,
+                &[CpuidFeature::AVX][..],
+                None
+            )),
+            Some(&Usage { count: 1, bytes: 4 })
+        );
+        assert_eq!(
+            counts.get(&(
+                "sse",
+                Mnemonic::Movaps,
+                &[CpuidFeature::SSE][..],
+                None
+            )),
+            Some(&Usage { count: 1, bytes: 3 })
+        );
+        assert_eq!(counts.len(), 4);
+    }
+
+    /// Code of the `sse` and `avx` functions in the PE and Mach-O
+    /// fixtures below.
+    #[rustfmt::skip]
+    pub(crate) const OBJECT_CODE: &[u8] = &[
+        0x0f, 0x28, 0x06,        // movaps xmm0,XMMWORD PTR [rsi]
+        0xc3,                    // ret
+        0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
+        0xc3,                    // ret
+    ];
+
+    fn write_at(data: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Builds a PE32+ DLL with `OBJECT_CODE` in `.text` at RVA 0x1000
+    /// and an export table in `.rdata` that exports `sse` and `avx`.
+    fn pe_fixture() -> Vec<u8> {
+        let mut data = vec![0; 0x600];
+        let u16 = |x: u16| x.to_le_bytes();
+        let u32 = |x: u32| x.to_le_bytes();
+
+        write_at(&mut data, 0, b"MZ");
+        write_at(&mut data, 0x3c, &u32(0x40));
+        write_at(&mut data, 0x40, b"PE\0\0");
+
+        // COFF header: x86-64, two sections, a PE32+ optional header,
+        // and the executable and DLL flags.
+        //
+        write_at(&mut data, 0x44, &u16(0x8664));
+        write_at(&mut data, 0x46, &u16(2));
+        write_at(&mut data, 0x54, &u16(0xf0));
+        write_at(&mut data, 0x56, &u16(0x2022));
+
+        // Optional header: image base, alignments, sizes, and the
+        // export directory.
+        //
+        write_at(&mut data, 0x58, &u16(0x20b));
+        write_at(&mut data, 0x70, &0x140000000u64.to_le_bytes());
+        write_at(&mut data, 0x78, &u32(0x1000));
+        write_at(&mut data, 0x7c, &u32(0x200));
+        write_at(&mut data, 0x90, &u32(0x3000));
+        write_at(&mut data, 0x94, &u32(0x200));
+        write_at(&mut data, 0x9c, &u16(3));
+        write_at(&mut data, 0xc4, &u32(16));
+        write_at(&mut data, 0xc8, &u32(0x2000));
+        write_at(&mut data, 0xcc, &u32(0x60));
+
+        // Section headers: name, virtual size and address, raw size
+        // and offset, and characteristics.
+        //
+        for (index, (name, size, address, characteristics)) in [
+            (b".text\0\0\0", OBJECT_CODE.len(), 0x1000, 0x60000020),
+            (b".rdata\0\0", 0x60, 0x2000, 0x40000040),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let header = 0x148 + index * 40;
+
+            write_at(&mut data, header, name);
+            write_at(&mut data, header + 8, &u32(size as u32));
+            write_at(&mut data, header + 12, &u32(address));
+            write_at(&mut data, header + 16, &u32(0x200));
+            write_at(&mut data, header + 20, &u32(address / 8));
+            write_at(&mut data, header + 36, &u32(characteristics));
+        }
+
+        write_at(&mut data, 0x200, OBJECT_CODE);
+
+        // Export directory at RVA 0x2000, followed by the address,
+        // name, and ordinal tables.  Names are sorted.
+        //
+        write_at(&mut data, 0x40c, &u32(0x2040));
+        write_at(&mut data, 0x410, &u32(1));
+        write_at(&mut data, 0x414, &u32(2));
+        write_at(&mut data, 0x418, &u32(2));
+        write_at(&mut data, 0x41c, &u32(0x2028));
+        write_at(&mut data, 0x420, &u32(0x2030));
+        write_at(&mut data, 0x424, &u32(0x2038));
+        write_at(&mut data, 0x428, &u32(0x1000));
+        write_at(&mut data, 0x42c, &u32(0x1004));
+        write_at(&mut data, 0x430, &u32(0x2048));
+        write_at(&mut data, 0x434, &u32(0x2050));
+        write_at(&mut data, 0x438, &u16(1));
+        write_at(&mut data, 0x43a, &u16(0));
+        write_at(&mut data, 0x440, b"t.dll\0");
+        write_at(&mut data, 0x448, b"avx\0");
+        write_at(&mut data, 0x450, b"sse\0");
+
+        data
+    }
+
+    /// Builds an x86-64 Mach-O object with `OBJECT_CODE` in
+    /// `__TEXT,__text` at 0x100, an external `_sse` symbol, and a
+    /// private `_avx` symbol.
+    fn mach_fixture() -> Vec<u8> {
+        let mut data = Vec::new();
+        let u32 = |x: u32| x.to_le_bytes();
+        let u64 = |x: u64| x.to_le_bytes();
+
+        // Header: magic, CPU type, subtype, file type, two load
+        // commands, and their size.
+        //
+        for (index, value) in [0xfeedfacf, 0x01000007, 3, 1, 2, 176]
+            .into_iter()
+            .enumerate()
+        {
+            write_at(&mut data, index * 4, &u32(value));
+        }
+
+        // LC_SEGMENT_64 with a single section.
+        //
+        write_at(&mut data, 0x20, &u32(0x19));
+        write_at(&mut data, 0x24, &u32(152));
+        write_at(&mut data, 0x28, b"__TEXT");
+        write_at(&mut data, 0x38, &u64(0x100));
+        write_at(&mut data, 0x40, &u64(OBJECT_CODE.len() as u64));
+        write_at(&mut data, 0x48, &u64(0x100));
+        write_at(&mut data, 0x50, &u64(OBJECT_CODE.len() as u64));
+        write_at(&mut data, 0x58, &u32(7));
+        write_at(&mut data, 0x5c, &u32(5));
+        write_at(&mut data, 0x60, &u32(1));
+        write_at(&mut data, 0x68, b"__text");
+        write_at(&mut data, 0x78, b"__TEXT");
+        write_at(&mut data, 0x88, &u64(0x100));
+        write_at(&mut data, 0x90, &u64(OBJECT_CODE.len() as u64));
+        write_at(&mut data, 0x98, &u32(0x100));
+        write_at(
+            &mut data,
+            0xa8,
+            &u32(S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS),
+        );
+
+        // LC_SYMTAB: two symbols and their names.
+        //
+        for (index, value) in
+            [0x2, 24, 0x110, 2, 0x130, 11].into_iter().enumerate()
+        {
+            write_at(&mut data, 0xb8 + index * 4, &u32(value));
+        }
+
+        write_at(&mut data, 0x100, OBJECT_CODE);
+
+        for (index, (name, n_type, address)) in
+            [(1, 0x0f, 0x100), (6, 0x0e, 0x104)].into_iter().enumerate()
+        {
+            let nlist = 0x110 + index * 16;
+
+            write_at(&mut data, nlist, &u32(name));
+            write_at(&mut data, nlist + 4, &[n_type, 1]);
+            write_at(&mut data, nlist + 8, &u64(address));
+        }
+
+        write_at(&mut data, 0x130, b"\0_sse\0_avx\0");
+
+        data
+    }
+
+    /// Builds an x86-64 ELF executable with `OBJECT_CODE` in `.text` at
+    /// 0x400100, in a single loadable segment that maps the file at
+    /// 0x400000, with the global functions `sse` and `avx` in
+    /// `.symtab`, and a `.debug_info` section.
+    pub(crate) fn elf_fixture() -> Vec<u8> {
+        let mut data = Vec::new();
+        let u16 = |x: u16| x.to_le_bytes();
+        let u32 = |x: u32| x.to_le_bytes();
+        let u64 = |x: u64| x.to_le_bytes();
+        let shstrtab =
+            b"\0.text\0.debug_info\0.symtab\0.strtab\0.shstrtab\0";
+
+        // Header: ET_EXEC, EM_X86_64, the entry point, and the offsets
+        // of one program header and six section headers.
+        //
+        write_at(&mut data, 0, b"\x7fELF\x02\x01\x01");
+        write_at(&mut data, 0x10, &u16(2));
+        write_at(&mut data, 0x12, &u16(62));
+        write_at(&mut data, 0x14, &u32(1));
+        write_at(&mut data, 0x18, &u64(0x400100));
+        write_at(&mut data, 0x20, &u64(0x40));
+        write_at(&mut data, 0x28, &u64(0x200));
+        write_at(&mut data, 0x34, &u16(64));
+        write_at(&mut data, 0x36, &u16(56));
+        write_at(&mut data, 0x38, &u16(1));
+        write_at(&mut data, 0x3a, &u16(64));
+        write_at(&mut data, 0x3c, &u16(6));
+        write_at(&mut data, 0x3e, &u16(5));
+
+        // PT_LOAD, readable and executable, up to the end of `.text`.
+        //
+        let end = 0x100 + OBJECT_CODE.len() as u64;
+
+        write_at(&mut data, 0x40, &u32(1));
+        write_at(&mut data, 0x44, &u32(5));
+        write_at(&mut data, 0x50, &u64(0x400000));
+        write_at(&mut data, 0x58, &u64(0x400000));
+        write_at(&mut data, 0x60, &u64(end));
+        write_at(&mut data, 0x68, &u64(end));
+        write_at(&mut data, 0x70, &u64(0x1000));
+
+        write_at(&mut data, 0x100, OBJECT_CODE);
+        write_at(&mut data, 0x110, &[0x11; 32]);
+
+        // Symbols after the null symbol: name, STB_GLOBAL and
+        // STT_FUNC, section index, value, and size.
+        //
+        for (index, (name, address, size)) in
+            [(1, 0x400100, 4), (5, 0x400104, 5)].into_iter().enumerate()
+        {
+            let sym = 0x148 + index * 24;
+
+            write_at(&mut data, sym, &u32(name));
+            write_at(&mut data, sym + 4, &[0x12, 0]);
+            write_at(&mut data, sym + 6, &u16(1));
+            write_at(&mut data, sym + 8, &u64(address));
+            write_at(&mut data, sym + 16, &u64(size));
+        }
+
+        write_at(&mut data, 0x178, b"\0sse\0avx\0");
+        write_at(&mut data, 0x188, shstrtab);
+
+        // Section headers after the null section: name, type, flags,
+        // address, offset, size, link, info, alignment, and entry
+        // size.
+        //
+        for (index, header) in [
+            [1, 1, 6, 0x400100, 0x100, end - 0x100, 0, 0, 16, 0],
+            [7, 1, 0, 0, 0x110, 32, 0, 0, 1, 0],
+            [19, 2, 0, 0, 0x130, 72, 4, 1, 8, 24],
+            [27, 3, 0, 0, 0x178, 9, 0, 0, 1, 0],
+            [35, 3, 0, 0, 0x188, shstrtab.len() as u64, 0, 0, 1, 0],
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let shdr = 0x200 + (index + 1) * 64;
+
+            write_at(&mut data, shdr, &u32(header[0] as u32));
+            write_at(&mut data, shdr + 4, &u32(header[1] as u32));
+
+            for (field, &value) in header[2..6].iter().enumerate() {
+                write_at(&mut data, shdr + 8 + field * 8, &u64(value));
+            }
+
+            write_at(&mut data, shdr + 40, &u32(header[6] as u32));
+            write_at(&mut data, shdr + 44, &u32(header[7] as u32));
+            write_at(&mut data, shdr + 48, &u64(header[8]));
+            write_at(&mut data, shdr + 56, &u64(header[9]));
+        }
+
+        data.resize(0x200 + 6 * 64, 0);
+
+        data
+    }
+
+    #[test]
+    fn from_pe() {
+        let data = pe_fixture();
+        let binary = Binary::from_bytes(&data, 0, &[]).unwrap();
+        let counts =
+            binary.instruction_counts_by_symbol(false, None).unwrap();
+
+        assert!(is_pe_or_mach(&data));
+        assert!(is_x86_object(&data));
+        assert_eq!(binary.format(), "pe");
+        assert_eq!(binary.bitness(), 64);
+        assert_eq!(
+            binary.symbol_starts().collect::<Vec<_>>(),
+            [("sse", 0x140001000), ("avx", 0x140001004)]
+        );
+        assert!(binary.is_exported("sse") && binary.is_exported("avx"));
+        assert_eq!(
+            counts.get(&(
+                "avx",
+                Mnemonic::Vmovaps,
+                &[CpuidFeature::AVX][..],
+                None
+            )),
+            Some(&Usage { count: 1, bytes: 4 })
+        );
+        assert_eq!(counts.len(), 4);
+
+        // A PE file for another architecture is not an x86 object.
+        //
+        let mut arm64 = data.clone();
+
+        write_at(&mut arm64, 0x44, &0xaa64u16.to_le_bytes());
+
+        assert!(is_pe_or_mach(&arm64));
+        assert!(!is_x86_object(&arm64));
+    }
+
+    #[test]
+    fn from_mach() {
+        let data = mach_fixture();
+        let binary = Binary::from_bytes(&data, 0, &[]).unwrap();
+        let counts =
+            binary.instruction_counts_by_symbol(false, None).unwrap();
+
+        assert!(is_pe_or_mach(&data));
+        assert!(is_x86_object(&data));
+        assert_eq!(binary.format(), "mach-o");
+        assert_eq!(binary.bitness(), 64);
+        assert_eq!(
+            binary.symbol_starts().collect::<Vec<_>>(),
+            [("_sse", 0x100), ("_avx", 0x104)]
+        );
+        assert!(binary.is_exported("_sse"));
+        assert!(!binary.is_exported("_avx"));
+        assert_eq!(
+            counts.get(&(
+                "_sse",
+                Mnemonic::Movaps,
+                &[CpuidFeature::SSE][..],
+                None
+            )),
+            Some(&Usage { count: 1, bytes: 3 })
+        );
+        assert_eq!(counts.len(), 4);
+        assert!(!is_pe_or_mach(
+            b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0"
+        ));
+    }
+
+    #[test]
+    fn relocate() {
+        #[rustfmt::skip]
+        let code: &[u8] = &[
+            0x0f, 0x28, 0x06,        // movaps xmm0,XMMWORD PTR [rsi]
+            0xc3,                    // ret
+            0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
+            0xc3,                    // ret
+        ];
+        let symbols = vec![
+            (0x400, "sse".to_string()),
+            (0x404, "avx".to_string()),
+        ];
+        let mut binary = Binary::from_raw(
+            code,
+            0x400,
+            64,
+            &symbols,
+            DecoderOptions::NONE,
+            &[],
+        )
+        .unwrap();
+
+        binary.relocate(0x404..0x1000, 0x7f0000);
+
+        let regions = binary.regions();
+        let counts =
+            binary.instruction_counts_by_symbol(false, None).unwrap();
+
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].address, 0x7f0000);
+        assert_eq!(regions[0].size(), 5);
+        assert!(counts.keys().all(|&(name, ..)| name == "avx"));
+        assert_eq!(counts.len(), 2);
+    }
+
+    #[test]
+    fn raw_slice_bounds() {
+        let data = [0u8; 8];
+
+        assert_eq!(raw_slice(&data, 2, Some(4)).unwrap().len(), 4);
+        assert_eq!(raw_slice(&data, 2, None).unwrap().len(), 6);
+        assert!(raw_slice(&data, 6, Some(4)).is_err());
+        assert!(raw_slice(&data, 9, None).is_err());
+    }
+
+    #[test]
+    fn section_bounds() {
+        let data = [0xc3u8; 16];
+        let ctx = Ctx::new(Container::Big, Endian::Little);
+        let section_hdr =
+            |sh_offset: u64, sh_size: u64| elf::SectionHeader {
+                sh_offset,
+                sh_size,
+                ..Default::default()
+            };
+
+        // A section that ends exactly at the end of the file.
+        //
+        assert_eq!(
+            section_contents("a", &section_hdr(12, 4), &data, ctx)
+                .unwrap(),
+            [0xc3; 4]
+        );
+        assert!(section_contents("a", &section_hdr(12, 5), &data, ctx)
+            .is_err());
+        assert!(section_contents(
+            "a",
+            &section_hdr(12, u64::MAX - 4),
+            &data,
+            ctx
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn overlapping_overrides() {
+        let code = [0x90u8; 16];
+        let mut binary = Binary::from_raw(
+            &code,
+            0x400,
+            64,
+            &[],
+            DecoderOptions::NONE,
+            &[],
+        )
+        .unwrap();
+
+        for (name, begin, end) in
+            [("a", 2, 8), ("b", 4, 6), ("c", 6, 12)]
+        {
+            binary.symbols.push((
+                name.to_string(),
+                SymbolRange {
+                    section: 0,
+                    begin,
+                    end,
+                    bitness: 32,
+                },
+            ));
+        }
+
+        let regions: Vec<_> = binary
+            .regions()
+            .iter()
+            .map(|x| (x.name, x.address, x.code.len()))
+            .collect();
+
+        assert_eq!(
+            regions,
+            [
+                (binary.sections[0].name.as_str(), 0x400, 2),
+                ("a", 0x402, 6),
+                ("c", 0x408, 4),
+                (binary.sections[0].name.as_str(), 0x40c, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_addresses() {
+        // Offsets of the `sh_addr` field of `.text` and of the fields
+        // of `.debug_info` in `elf_fixture`.
+        //
+        const TEXT_ADDR: usize = 0x200 + 64 + 16;
+        const DEBUG_INFO: usize = 0x200 + 2 * 64;
+
+        let mut data = elf_fixture();
+
+        write_at(&mut data, TEXT_ADDR, &u64::MAX.to_le_bytes());
+
+        assert!(Binary::from_bytes(&data, 0, &[])
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("past the end of the address space"));
+
+        // In a relocatable object, `.text` is at 0, and the symbol
+        // values are relative to it.
+        //
+        let mut data = elf_fixture();
+
+        write_at(&mut data, 0x10, &elf::header::ET_REL.to_le_bytes());
+        write_at(&mut data, TEXT_ADDR, &0_u64.to_le_bytes());
+        write_at(&mut data, 0x148 + 8, &u64::MAX.to_le_bytes());
+        write_at(&mut data, 0x160 + 8, &4_u64.to_le_bytes());
+
+        let binary = Binary::from_bytes(&data, 0, &[]).unwrap();
+
+        assert_eq!(
+            binary.symbol_starts().collect::<Vec<_>>(),
+            [("avx", 4)]
+        );
+
+        // A code section whose alignment cannot be satisfied.
+        //
+        write_at(&mut data, DEBUG_INFO + 8, &6_u64.to_le_bytes());
+        write_at(&mut data, DEBUG_INFO + 48, &u64::MAX.to_le_bytes());
+
+        assert!(Binary::from_bytes(&data, 0, &[])
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("cannot be laid out"));
+    }
+
+    #[test]
+    fn perf_map_symbols() {
+        let mut binary = Binary::from_raw(
+            OBJECT_CODE,
+            0x400,
+            64,
+            &[],
+            DecoderOptions::NONE,
+            &[],
+        )
+        .unwrap();
+
+        // The size of the last symbol overflows the address space.
+        //
+        binary.add_symbols(&[
+            (0x400, 4, "sse".to_string()),
+            (0x404, usize::MAX, "avx".to_string()),
+            (0x1000, 4, "outside".to_string()),
+        ]);
+
+        let ranges: Vec<_> = binary
+            .symbols
+            .iter()
+            .map(|(name, range)| {
+                (name.as_str(), range.begin, range.end)
+            })
+            .collect();
+
+        assert_eq!(
+            ranges,
+            [("sse", 0, 4), ("avx", 4, OBJECT_CODE.len())]
+        );
+    }
+
+    #[test]
+    fn exported_functions() {
+        use elf::sym::{
+            STB_GLOBAL, STB_LOCAL, STB_WEAK, STT_FUNC, STT_OBJECT,
+            STV_HIDDEN,
+        };
+
+        let sym =
+            |bind: u8, typ: u8, other: u8, shndx: usize| elf::Sym {
+                st_info: (bind << 4) | typ,
+                st_other: other,
+                st_shndx: shndx,
+                st_value: 0x1000,
+                ..Default::default()
+            };
+
+        assert!(is_exported_function(&sym(
+            STB_GLOBAL, STT_FUNC, 0, 12
+        )));
+        assert!(is_exported_function(&sym(STB_WEAK, STT_FUNC, 0, 12)));
+        assert!(!is_exported_function(&sym(
+            STB_LOCAL, STT_FUNC, 0, 12
+        )));
+        assert!(!is_exported_function(&sym(
+            STB_GLOBAL, STT_OBJECT, 0, 12
+        )));
+        assert!(!is_exported_function(&sym(
+            STB_GLOBAL, STT_FUNC, STV_HIDDEN, 12
+        )));
+        // Undefined symbols are imported, not exported:
+        assert!(!is_exported_function(&sym(
+            STB_GLOBAL, STT_FUNC, 0, 0
+        )));
+    }
+
+    #[test]
+    fn from_bytes() {
+        use std::io::Write;
+
+        let data = elf_fixture();
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+
+        encoder.write_all(&data).unwrap();
+
+        let compressed = encoder.finish().unwrap();
+
+        for data in [&data, &compressed] {
+            let binary = Binary::from_bytes(data, 0, &[]).unwrap();
+
+            assert!(!binary.regions().is_empty());
+        }
+
+        assert!(Binary::from_bytes(b"not a binary", 0, &[]).is_err());
+    }
+
+    /// Compresses the sections of the 64-bit ELF file `data` that
+    /// `compress` selects with zlib, like `objcopy
+    /// --compress-debug-sections` does with DWARF sections.  Their
+    /// contents and the section header table are moved to the end of
+    /// the file.
+    fn compress_sections(
+        data: &[u8],
+        compress: impl Fn(&str) -> bool,
+    ) -> Vec<u8> {
+        use std::io::Write;
+
+        let elf = elf::Elf::parse(data).unwrap();
+        let mut result = data.to_vec();
+        let mut headers = data[elf.header.e_shoff as usize..]
+            [..elf.header.e_shnum as usize * 64]
+            .to_vec();
+
+        for (index, section_hdr) in
+            elf.section_headers.iter().enumerate()
+        {
+            let name = elf.shdr_strtab.get_at(section_hdr.sh_name);
+
+            if !name.is_some_and(&compress) {
+                continue;
+            }
+
+            let mut encoder = flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            );
+
+            encoder
+                .write_all(&data[section_hdr.file_range().unwrap()])
+                .unwrap();
+            result.resize(result.len().next_multiple_of(8), 0);
+
+            let offset = result.len() as u64;
+
+            // Elf64_Chdr
+            //
+            result.extend(1_u32.to_le_bytes());
+            result.extend(0_u32.to_le_bytes());
+            result.extend(section_hdr.sh_size.to_le_bytes());
+            result.extend(section_hdr.sh_addralign.to_le_bytes());
+            result.extend(encoder.finish().unwrap());
+
+            let header = &mut headers[index * 64..][..64];
+            let flags = section_hdr.sh_flags
+                | u64::from(elf::section_header::SHF_COMPRESSED);
+
+            header[8..16].copy_from_slice(&flags.to_le_bytes());
+            header[24..32].copy_from_slice(&offset.to_le_bytes());
+            header[32..40].copy_from_slice(
+                &(result.len() as u64 - offset).to_le_bytes(),
+            );
+        }
+
+        result.resize(result.len().next_multiple_of(8), 0);
+
+        let shoff = result.len() as u64;
+
+        result[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        result.extend(headers);
+
+        result
+    }
+
+    #[test]
+    fn compressed_sections() {
+        let data = elf_fixture();
+        let contents = |data: &[u8]| {
+            let binary = Binary::from_bytes(data, 0, &[]).unwrap();
+            let symbols: Vec<(String, usize)> = binary
+                .symbol_starts()
+                .map(|(name, address)| (name.to_string(), address))
+                .collect();
+            let code: Vec<Vec<u8>> = binary
+                .regions()
+                .iter()
+                .map(|x| x.code.to_vec())
+                .collect();
+
+            (symbols, code)
+        };
+        let expected = contents(&data);
+
+        assert_eq!(
+            expected,
+            (
+                vec![
+                    ("sse".to_string(), 0x400100),
+                    ("avx".to_string(), 0x400104)
+                ],
+                vec![OBJECT_CODE.to_vec()]
+            )
+        );
+
+        // `.text` is decompressed when it is read, the other sections
+        // before the file is parsed.
+        //
+        for compress in [
+            |name: &str| name == ".text",
+            |name: &str| name.starts_with(".debug_"),
+            |name: &str| name == ".symtab" || name == ".strtab",
+        ] {
+            let compressed = compress_sections(&data, compress);
+
+            assert_ne!(compressed, data);
+            assert_eq!(contents(&compressed), expected);
+        }
     }
 }
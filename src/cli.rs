@@ -1,14 +1,53 @@
-use clap::{arg, command, ArgGroup};
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::{arg, command, ArgAction, ArgGroup};
+use std::path::Path;
 use wildmatch::WildMatch;
 
+use crate::binary::DECODER_OPTIONS;
+
+/// Filename that stands for stdin.
+pub const STDIN: &str = "-";
+
 #[derive(Debug)]
 pub struct Args {
     pub output_format: OutputFormat,
+    pub legacy_json: bool,
+    pub matrix: Option<MatrixFormat>,
+    pub checkmarks: bool,
     pub show_symbols: ShowSymbols,
     pub feature_filter: Vec<WildMatch>,
     pub raw_symbol_filter: Vec<WildMatch>,
     pub demangled_symbol_filter: Vec<WildMatch>,
-    pub binary_filename: String,
+    pub decoder_options: u32,
+    pub bitness: Option<u32>,
+    pub bitness_overrides: Vec<(String, u32)>,
+    pub raw: bool,
+    pub offset: usize,
+    pub length: Option<usize>,
+    pub base_address: usize,
+    pub symbol_map: Option<String>,
+    pub with_deps: bool,
+    pub sysroot: Option<String>,
+    pub group_by_encoding: bool,
+    pub group_by_family: bool,
+    pub vector_usage: bool,
+    pub check_kernel_fpu: bool,
+    pub zmm_only: bool,
+    pub exported_only: bool,
+    pub include: Vec<WildMatch>,
+    pub exclude: Vec<WildMatch>,
+    pub follow_symlinks: bool,
+    pub pid: Option<u32>,
+    pub anonymous: bool,
+    pub perf_map: Option<String>,
+    pub profile: Option<String>,
+    pub sizes: bool,
+    pub sort_by: SortBy,
+    pub reverse: bool,
+    pub top: Option<usize>,
+    pub summary: bool,
+    pub binary_filenames: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -16,6 +55,29 @@ pub enum OutputFormat {
     List,
     Table,
     Json,
+    Csv,
+    Tsv,
+}
+
+/// Order of extension sets, opcodes, and functions in the output.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum SortBy {
+    #[default]
+    Name,
+    /// Instructions, most first.
+    Count,
+    /// Code size, largest first.
+    Bytes,
+    /// Extension set, then name.
+    Feature,
+}
+
+/// Rendering of the function by extension set matrix.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MatrixFormat {
+    Table,
+    Csv,
+    Markdown,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -27,13 +89,82 @@ pub enum ShowSymbols {
 
 impl Args {
     pub fn parse() -> Self {
-        let matches = command!()
+        Self::parse_from(std::env::args_os())
+    }
+
+    /// Parses `args`, whose first item is the name of the program.
+    pub fn parse_from<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let mut command = command!()
             .arg(arg!(-l --list "Print output as list"))
             .arg(arg!(-t --table "Print output as table"))
             .arg(arg!(-j --json "Print output as JSON"))
+            .arg(
+                arg!(
+                    --"legacy-json"
+                    "Print JSON in the unversioned shape of earlier \
+                     releases"
+                )
+                .requires("json"),
+            )
+            .arg(
+                arg!(
+                    --csv
+                    "Print output as comma-separated values, one row \
+                     per file, function, extension set, and opcode"
+                )
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --tsv
+                    "Print output as tab-separated values, one row per \
+                     file, function, extension set, and opcode"
+                )
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
             .group(
                 ArgGroup::new("output-format")
-                    .args(["list", "table", "json"]),
+                    .args(["list", "table", "json", "csv", "tsv"]),
+            )
+            .arg(
+                arg!(
+                    --matrix [FORMAT]
+                    "Print one row per function and one column per \
+                     extension set, as a table, CSV, or Markdown \
+                     (implies --show-symbol)"
+                )
+                .value_parser(["table", "csv", "markdown"])
+                .require_equals(true)
+                .default_missing_value("table")
+                .conflicts_with_all([
+                    "list",
+                    "json",
+                    "csv",
+                    "tsv",
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --checkmarks
+                    "Mark the extension sets that each function uses \
+                     instead of counting instructions in --matrix output"
+                )
+                .requires("matrix"),
             )
             .arg(arg!(
                     -s --"show-symbol"
@@ -62,10 +193,270 @@ impl Args {
                     "Comma-separated list of demangled symbol names to \
                      include in the output (can include wildcards)"
             ))
+            .arg(arg!(
+                    -w --"vector-usage"
+                    "Summarize vector register widths and AVX-512 \
+                     masking, broadcast, and rounding instead of \
+                     extension sets"
+            ))
             .arg(
-                arg!(<BINARY_FILENAME> "Filename of binary to inspect"),
+                arg!(
+                    --"check-kernel-fpu"
+                    "List kernel functions that use SIMD instructions \
+                     without calling kernel_fpu_begin"
+                )
+                .conflicts_with("vector-usage"),
             )
-            .get_matches();
+            .arg(arg!(
+                    --"zmm-only"
+                    "Only include functions that use zmm registers"
+            ))
+            .arg(
+                arg!(
+                    --"exported-only"
+                    "Only include functions that a shared library \
+                     exports in its dynamic symbol table"
+                )
+                .conflicts_with("raw"),
+            )
+            .arg(
+                arg!(
+                    --"decoder-option" <NAME>
+                    "Enable an iced-x86 decoder option, e.g., 'amd', \
+                     'knc', 'mpx', or 'cyrix' (can be repeated)"
+                )
+                .action(ArgAction::Append)
+                .value_parser(
+                    PossibleValuesParser::new(
+                        DECODER_OPTIONS.iter().map(|&(name, _)| name),
+                    ),
+                ),
+            )
+            .arg(
+                arg!(
+                    --bitness <BITNESS>
+                    "Decode the input (with --raw), or a section or \
+                     symbol given as NAME=BITS (e.g., '.text16=16'), as \
+                     16-, 32-, or 64-bit code (can be repeated)"
+                )
+                .action(ArgAction::Append)
+                .value_parser(parse_bitness),
+            )
+            .arg(arg!(
+                    --raw
+                    "Treat the input as raw code instead of an ELF binary"
+            ))
+            .arg(
+                arg!(
+                    --offset <BYTES>
+                    "Offset of the code within the input (with --raw)"
+                )
+                .value_parser(parse_number)
+                .requires("raw"),
+            )
+            .arg(
+                arg!(
+                    --length <BYTES>
+                    "Length of the code within the input (with --raw)"
+                )
+                .value_parser(parse_number)
+                .requires("raw"),
+            )
+            .arg(
+                arg!(
+                    --"base-address" <ADDRESS>
+                    "Address at which the code is loaded (with --raw)"
+                )
+                .value_parser(parse_number)
+                .requires("raw"),
+            )
+            .arg(
+                arg!(
+                    --"symbol-map" <FILE>
+                    "File with one 'ADDRESS NAME' pair per line, or the \
+                     output of nm(1) (with --raw)"
+                )
+                .requires("raw"),
+            )
+            .arg(
+                arg!(
+                    --"with-deps"
+                    "Also inspect the shared libraries that the binary \
+                     depends on"
+                )
+                .conflicts_with_all([
+                    "raw",
+                    "vector-usage",
+                    "check-kernel-fpu",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --sysroot <DIR>
+                    "Look up shared libraries relative to this directory \
+                     (with --with-deps)"
+                )
+                .requires("with-deps"),
+            )
+            .arg(
+                arg!(
+                    --"group-by" <DIMENSION>
+                    "Additional dimension to group instructions by \
+                     (can be repeated)"
+                )
+                .action(ArgAction::Append)
+                .value_parser(["encoding", "family"]),
+            )
+            .arg(
+                arg!(
+                    --include <GLOB>
+                    "When inspecting directories, only inspect files \
+                     whose name or path matches this pattern (can be \
+                     repeated)"
+                )
+                .action(ArgAction::Append),
+            )
+            .arg(
+                arg!(
+                    --exclude <GLOB>
+                    "Skip files and directories whose name or path \
+                     matches this pattern (can be repeated)"
+                )
+                .action(ArgAction::Append),
+            )
+            .arg(arg!(
+                    --"follow-symlinks"
+                    "Follow symbolic links in directories"
+            ))
+            .arg(
+                arg!(
+                    --pid <PID>
+                    "Inspect the code mapped into a running process"
+                )
+                .value_parser(clap::value_parser!(u32))
+                .conflicts_with_all([
+                    "raw",
+                    "with-deps",
+                    "vector-usage",
+                    "check-kernel-fpu",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --anonymous
+                    "Also read anonymous executable mappings, e.g., JIT \
+                     code, from the memory of the process (with --pid)"
+                )
+                .requires("pid"),
+            )
+            .arg(arg!(
+                    --"perf-map" <FILE>
+                    "Name JIT code after the functions in a perf map such \
+                     as /tmp/perf-PID.map (with --raw or --pid, which reads \
+                     it by default)"
+            ))
+            .arg(
+                arg!(
+                    --profile <FILE>
+                    "Weight instructions by the samples in a perf.data \
+                     file or the output of 'perf script', and report the \
+                     share of samples of each extension set"
+                )
+                .conflicts_with_all([
+                    "with-deps",
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "pid",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --size
+                    "Also report the size in bytes of the instructions of \
+                     each extension set, opcode, and function, and their \
+                     share of the code"
+                )
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --"sort-by-size"
+                    "Same as --sort-by bytes"
+                )
+                .conflicts_with_all([
+                    "sort-by",
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --"sort-by" <KEY>
+                    "Sort by name, by number of instructions or bytes \
+                     (most first), or by extension set ('bytes' implies \
+                     --size)"
+                )
+                .value_parser(["name", "count", "bytes", "feature"])
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --reverse
+                    "Reverse the order of the output"
+                )
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --top <N>
+                    "Only print the first N rows, extension sets, or \
+                     functions of each list"
+                )
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    --summary
+                    "Also report totals per function and for the binary, \
+                     the share of each row in them, and the share of SIMD \
+                     instructions"
+                )
+                .conflicts_with_all([
+                    "vector-usage",
+                    "check-kernel-fpu",
+                    "profile",
+                ]),
+            )
+            .arg(
+                arg!(
+                    [BINARY_FILENAME]...
+                    "Filenames of binaries or directories to inspect, or \
+                     '-' to read a binary from stdin"
+                )
+                .required_unless_present("pid")
+                .conflicts_with("pid"),
+            );
+        let matches = command
+            .try_get_matches_from_mut(args)
+            .unwrap_or_else(|error| error.exit());
 
         let output_format =
             if *matches.get_one("list").expect("should be Some") {
@@ -73,6 +464,10 @@ impl Args {
             } else if *matches.get_one("json").expect("should be Some")
             {
                 OutputFormat::Json
+            } else if *matches.get_one("csv").expect("should be Some") {
+                OutputFormat::Csv
+            } else if *matches.get_one("tsv").expect("should be Some") {
+                OutputFormat::Tsv
             } else {
                 // Default:
                 OutputFormat::Table
@@ -87,10 +482,24 @@ impl Args {
                 .expect("should be Some")
             {
                 ShowSymbols::Demangled
+            } else if matches.contains_id("matrix") {
+                ShowSymbols::Raw
             } else {
                 // Default:
                 ShowSymbols::No
             };
+        let legacy_json =
+            *matches.get_one("legacy-json").expect("should be Some");
+        let matrix =
+            matches.get_one::<String>("matrix").map(|x| {
+                match x.as_str() {
+                    "csv" => MatrixFormat::Csv,
+                    "markdown" => MatrixFormat::Markdown,
+                    _ => MatrixFormat::Table,
+                }
+            });
+        let checkmarks =
+            *matches.get_one("checkmarks").expect("should be Some");
 
         let feature_filter = matches
             .get_one::<String>("feature-filter")
@@ -104,18 +513,199 @@ impl Args {
             .get_one::<String>("demangled-symbol-filter")
             .map(|x| parse_filter(x))
             .unwrap_or_default();
-        let binary_filename = matches
-            .get_one::<String>("BINARY_FILENAME")
+        let decoder_options = matches
+            .get_many::<String>("decoder-option")
+            .unwrap_or_default()
+            .filter_map(|name| {
+                DECODER_OPTIONS
+                    .iter()
+                    .find(|&&(x, _)| x == name)
+                    .map(|&(_, bit)| bit)
+            })
+            .fold(0, |acc, bit| acc | bit);
+        let mut bitness = None;
+        let mut bitness_overrides = Vec::new();
+
+        for (name, bits) in matches
+            .get_many::<(Option<String>, u32)>("bitness")
+            .unwrap_or_default()
+            .cloned()
+        {
+            match name {
+                Some(name) => bitness_overrides.push((name, bits)),
+                None => bitness = Some(bits),
+            }
+        }
+
+        let raw: bool =
+            *matches.get_one("raw").expect("should be Some");
+
+        if bitness.is_some() && !raw {
+            command
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--bitness without NAME= requires --raw",
+                )
+                .exit();
+        }
+
+        let offset =
+            matches.get_one("offset").copied().unwrap_or_default();
+        let length = matches.get_one("length").copied();
+        let base_address = matches
+            .get_one("base-address")
+            .copied()
+            .unwrap_or_default();
+        let symbol_map =
+            matches.get_one::<String>("symbol-map").cloned();
+        let with_deps =
+            *matches.get_one("with-deps").expect("should be Some");
+        let sysroot = matches.get_one::<String>("sysroot").cloned();
+        let group_by = |dimension: &str| {
+            matches
+                .get_many::<String>("group-by")
+                .unwrap_or_default()
+                .any(|x| x == dimension)
+        };
+        let group_by_encoding = group_by("encoding");
+        let group_by_family = group_by("family");
+        let vector_usage =
+            *matches.get_one("vector-usage").expect("should be Some");
+        let check_kernel_fpu = *matches
+            .get_one("check-kernel-fpu")
+            .expect("should be Some");
+        let zmm_only =
+            *matches.get_one("zmm-only").expect("should be Some");
+        let exported_only =
+            *matches.get_one("exported-only").expect("should be Some");
+        let include = matches
+            .get_many::<String>("include")
+            .unwrap_or_default()
+            .map(|x| WildMatch::new(x))
+            .collect();
+        let exclude = matches
+            .get_many::<String>("exclude")
+            .unwrap_or_default()
+            .map(|x| WildMatch::new(x))
+            .collect();
+        let follow_symlinks = *matches
+            .get_one("follow-symlinks")
+            .expect("should be Some");
+        let pid = matches.get_one("pid").copied();
+        let anonymous =
+            *matches.get_one("anonymous").expect("should be Some");
+        let perf_map = matches.get_one::<String>("perf-map").cloned();
+
+        if perf_map.is_some() && !raw && pid.is_none() {
+            command
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--perf-map requires --raw or --pid",
+                )
+                .exit();
+        }
+
+        let profile = matches.get_one::<String>("profile").cloned();
+        let sort_by = if *matches
+            .get_one("sort-by-size")
+            .expect("should be Some")
+        {
+            SortBy::Bytes
+        } else {
+            match matches
+                .get_one::<String>("sort-by")
+                .map(String::as_str)
+            {
+                Some("count") => SortBy::Count,
+                Some("bytes") => SortBy::Bytes,
+                Some("feature") => SortBy::Feature,
+                _ => SortBy::Name,
+            }
+        };
+        let reverse =
+            *matches.get_one("reverse").expect("should be Some");
+        let top = matches.get_one("top").copied();
+        let sizes = sort_by == SortBy::Bytes
+            || *matches.get_one("size").expect("should be Some");
+        let summary =
+            *matches.get_one("summary").expect("should be Some");
+        let binary_filenames: Vec<String> = matches
+            .get_many::<String>("BINARY_FILENAME")
+            .unwrap_or_default()
             .cloned()
-            .expect("required");
+            .collect();
+
+        // Samples in a profile are attributed to the binary by its file
+        // name, which stdin does not have.
+        //
+        if binary_filenames.iter().any(|x| x == STDIN)
+            && (binary_filenames.len() > 1
+                || with_deps
+                || profile.is_some())
+        {
+            command
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "'-' can only be used as the only binary and without \
+                     --with-deps or --profile",
+                )
+                .exit();
+        }
+
+        if (raw
+            || vector_usage
+            || check_kernel_fpu
+            || profile.is_some())
+            && (binary_filenames.len() > 1
+                || Path::new(&binary_filenames[0]).is_dir())
+        {
+            command
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--raw, --vector-usage, --check-kernel-fpu, and \
+                     --profile require a single binary",
+                )
+                .exit();
+        }
 
         Self {
             output_format,
+            legacy_json,
+            matrix,
+            checkmarks,
             show_symbols,
             feature_filter,
             raw_symbol_filter,
             demangled_symbol_filter,
-            binary_filename,
+            decoder_options,
+            bitness,
+            bitness_overrides,
+            raw,
+            offset,
+            length,
+            base_address,
+            symbol_map,
+            with_deps,
+            sysroot,
+            group_by_encoding,
+            group_by_family,
+            vector_usage,
+            check_kernel_fpu,
+            zmm_only,
+            exported_only,
+            include,
+            exclude,
+            follow_symlinks,
+            pid,
+            anonymous,
+            perf_map,
+            profile,
+            sizes,
+            sort_by,
+            reverse,
+            top,
+            summary,
+            binary_filenames,
         }
     }
 }
@@ -129,3 +719,31 @@ fn parse_filter(filter: &str) -> Vec<WildMatch> {
 
     result
 }
+
+/// Parses either `BITS` or `NAME=BITS`.
+fn parse_bitness(value: &str) -> Result<(Option<String>, u32), String> {
+    let (name, bitness) = match value.rsplit_once('=') {
+        Some((name, bitness)) => (Some(name.to_string()), bitness),
+        None => (None, value),
+    };
+
+    match bitness {
+        "16" | "32" | "64" => Ok((
+            name,
+            bitness.parse().expect("should be a valid number"),
+        )),
+        _ => Err(format!(
+            "invalid bitness '{bitness}', expected 16, 32, or 64"
+        )),
+    }
+}
+
+/// Parses a decimal number or a hexadecimal number prefixed with `0x`.
+fn parse_number(value: &str) -> Result<usize, String> {
+    let result = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    result.map_err(|error| format!("invalid number '{value}': {error}"))
+}
@@ -1,14 +1,17 @@
+use iced_x86::{CpuidFeature, EncodingKind};
 use itertools::Itertools;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
-use std::fmt::Debug;
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Display};
 use symbolic_common::Name;
 use symbolic_demangle::{Demangle, DemangleOptions};
 use wildmatch::WildMatch;
 
-use crate::binary::Binary;
-use crate::cli::ShowSymbols;
+use crate::binary::{Binary, Usage, VectorUsage};
+use crate::cli::{MatrixFormat, ShowSymbols, SortBy};
+use crate::family;
 
 #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Instruction(String);
@@ -19,371 +22,2950 @@ pub struct Feature(String);
 #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ConcatenatedFeatures(String);
 
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Encoding(String);
+
 #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Symbol(String);
 
+/// Extension sets used by an instruction, qualified by the encoding of
+/// the instruction if grouping by encoding was requested.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct Extension {
+    features: ConcatenatedFeatures,
+    encoding: Option<Encoding>,
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ConcatenatedFeatures(features) = &self.features;
+
+        match &self.encoding {
+            Some(Encoding(encoding)) => {
+                write!(f, "{features} ({encoding})")
+            }
+            None => write!(f, "{features}"),
+        }
+    }
+}
+
+impl Serialize for Extension {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+type ExtensionUsage = BTreeMap<Extension, BTreeMap<Instruction, Usage>>;
+
 pub enum Features {
     Total {
-        data: BTreeMap<
-            ConcatenatedFeatures,
-            BTreeMap<Instruction, usize>,
-        >,
+        data: ExtensionUsage,
+        totals: Totals,
     },
     BySymbol {
-        data: BTreeMap<
-            Symbol,
-            BTreeMap<
-                ConcatenatedFeatures,
-                BTreeMap<Instruction, usize>,
-            >,
-        >,
+        data: BTreeMap<Symbol, ExtensionUsage>,
+        totals: Totals,
+        /// Instructions of each function in `data`, including those
+        /// of extension sets that are filtered out.
+        symbol_totals: BTreeMap<String, Usage>,
     },
 }
 
-fn lowercase(value: impl Debug) -> String {
-    format!("{value:?}").to_ascii_lowercase()
+/// Instructions of the binary, or of the functions that pass the
+/// symbol filter, including those of extension sets that are filtered
+/// out.
+#[derive(Default)]
+pub struct Totals {
+    usage: Usage,
+    /// Number of instructions that belong to a SIMD extension.
+    simd: usize,
+    /// Number of instructions of each SIMD extension.
+    simd_features: BTreeMap<String, usize>,
+    /// Every extension used, SIMD or not.
+    features: BTreeSet<String>,
 }
 
-fn width<T>(
-    title: &str,
-    items: impl Iterator<Item = T>,
-    length: impl Fn(T) -> usize,
-) -> usize {
-    let min_width = title.len();
+impl Totals {
+    fn add(&mut self, features: &[Feature], usage: Usage) {
+        let mut is_simd = false;
 
-    items
-        .map(length)
-        .max()
-        .filter(|&x| x > min_width)
-        .unwrap_or(min_width)
-}
+        self.usage += usage;
 
-pub fn get_features(
-    binary: &Binary,
-    feature_filter: &[WildMatch],
-    raw_symbol_filter: &[WildMatch],
-    demangled_symbol_filter: &[WildMatch],
-    show_symbols: ShowSymbols,
-) -> anyhow::Result<Features> {
-    if show_symbols != ShowSymbols::No || !raw_symbol_filter.is_empty()
-    {
-        let demangle_opts = DemangleOptions::complete();
-        let counts = binary
-            .instruction_counts_by_symbol()?
-            .into_iter()
-            .map(|((raw_symbol_name, mnemonic, features), counter)| {
-                let symbol_name = match show_symbols {
-                    ShowSymbols::No | ShowSymbols::Raw => {
-                        Symbol(raw_symbol_name.to_string())
-                    }
-                    ShowSymbols::Demangled => Symbol(
-                        Name::from(raw_symbol_name)
-                            .try_demangle(demangle_opts)
-                            .to_string(),
-                    ),
-                };
-                let feature_names: Vec<_> = features
-                    .iter()
-                    .map(|x| Feature(lowercase(x)))
-                    .collect();
+        for Feature(name) in features.iter() {
+            self.features.insert(name.clone());
 
-                (
-                    (
-                        symbol_name,
-                        feature_names,
-                        Instruction(lowercase(mnemonic)),
-                    ),
-                    counter,
-                )
-            })
-            .filter(|((_, features, _), _)| {
-                feature_filter.is_empty()
-                    || features.iter().any(|Feature(name)| {
-                        feature_filter
-                            .iter()
-                            .any(|pattern| pattern.matches(name))
-                    })
-            })
-            .filter(|((Symbol(raw_symbol_name), _, _), _)| {
-                (raw_symbol_filter.is_empty()
-                    && demangled_symbol_filter.is_empty())
-                    || raw_symbol_filter
-                        .iter()
-                        .any(|pattern| pattern.matches(raw_symbol_name))
-                    || demangled_symbol_filter.iter().any(|pattern| {
-                        let demangled = Name::from(raw_symbol_name)
-                            .try_demangle(demangle_opts)
-                            .to_string();
-                        pattern.matches(&demangled)
-                    })
+            if family::simd_width(name).is_some() {
+                *self.simd_features.entry(name.clone()).or_default() +=
+                    usage.count;
+                is_simd = true;
+            }
+        }
+
+        if is_simd {
+            self.simd += usage.count;
+        }
+    }
+
+    /// The SIMD extension with the widest registers, and of these the
+    /// one with the most instructions.
+    fn widest(&self) -> Option<String> {
+        self.simd_features
+            .iter()
+            .max_by_key(|&(name, &count)| {
+                (family::simd_width(name), count)
             })
-            .map(|((symbol, features, mnemonic), counter)| {
-                (
-                    (
+            .map(|(name, _)| display_name(name))
+    }
+
+    /// One-line summary such as "12.3% of instructions are SIMD;
+    /// widest: AVX-512F".
+    fn summary(&self) -> String {
+        let share = percentage(self.simd, self.usage.count);
+
+        match self.widest() {
+            Some(widest) => {
+                format!("{share} of instructions are SIMD; widest: {widest}")
+            }
+            None => format!("{share} of instructions are SIMD"),
+        }
+    }
+
+    /// Extensions that the ones used imply, e.g., AVX for AVX2, but
+    /// that no instruction uses itself.
+    fn implied(&self) -> BTreeSet<&'static str> {
+        family::implied(&self.features)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "instructions": self.usage.count,
+            "simd_instructions": self.simd,
+            "simd_share": ratio(self.simd, self.usage.count),
+            "widest": self.widest(),
+            "implied": self.implied(),
+        })
+    }
+}
+
+/// An instruction in `Features`, and the function it is in if counted
+/// by symbol.
+struct Row<'a> {
+    symbol: Option<&'a str>,
+    extension: &'a Extension,
+    mnemonic: &'a str,
+    usage: Usage,
+}
+
+impl Row<'_> {
+    fn sort_key(&self) -> SortKey<'_> {
+        SortKey {
+            extension: &self.extension.features.0,
+            usage: self.usage,
+        }
+    }
+}
+
+impl Features {
+    fn by_encoding(&self) -> bool {
+        match self {
+            Features::Total { data, .. } => data
+                .keys()
+                .any(|extension| extension.encoding.is_some()),
+            Features::BySymbol { data, .. } => {
+                data.values().any(|feature_counts| {
+                    feature_counts
+                        .keys()
+                        .any(|extension| extension.encoding.is_some())
+                })
+            }
+        }
+    }
+
+    fn totals(&self) -> &Totals {
+        match self {
+            Features::Total { totals, .. }
+            | Features::BySymbol { totals, .. } => totals,
+        }
+    }
+
+    /// Bytes of all instructions, including those that are filtered
+    /// out by extension set.
+    fn size(&self) -> usize {
+        self.totals().usage.bytes
+    }
+
+    /// Instructions of a function, including those that are filtered
+    /// out by extension set.
+    fn symbol_total(&self, symbol: &str) -> Usage {
+        match self {
+            Features::Total { .. } => Usage::default(),
+            Features::BySymbol { symbol_totals, .. } => {
+                symbol_totals.get(symbol).copied().unwrap_or_default()
+            }
+        }
+    }
+
+    fn rows(&self) -> Vec<Row<'_>> {
+        let by_symbol: Vec<(Option<&str>, &ExtensionUsage)> = match self
+        {
+            Features::Total { data, .. } => vec![(None, data)],
+            Features::BySymbol { data, .. } => data
+                .iter()
+                .map(|(Symbol(symbol), feature_counts)| {
+                    (Some(symbol.as_str()), feature_counts)
+                })
+                .collect(),
+        };
+        let mut result = Vec::new();
+
+        for (symbol, feature_counts) in by_symbol {
+            for (extension, counts) in feature_counts.iter() {
+                for (Instruction(mnemonic), usage) in counts.iter() {
+                    result.push(Row {
                         symbol,
-                        ConcatenatedFeatures(
-                            features
-                                .into_iter()
-                                .map(|Feature(x)| x)
-                                .join(","),
-                        ),
+                        extension,
                         mnemonic,
-                    ),
-                    counter,
-                )
-            });
-        let mut data = BTreeMap::new();
+                        usage: *usage,
+                    });
+                }
+            }
+        }
 
-        for ((symbol_name, feature_names, mnemonic), count) in counts {
-            data.entry(symbol_name)
-                .or_insert_with(BTreeMap::new)
-                .entry(feature_names)
-                .or_insert_with(BTreeMap::new)
-                .insert(mnemonic, count);
+        result
+    }
+
+    /// Rows in the requested order.  If counted by symbol, functions
+    /// are ranked by their instructions that pass the filters, `top`
+    /// keeps that many functions with all of their rows, and the rows
+    /// of each function are ordered among themselves.
+    fn sorted_rows(&self, options: &PrintOptions) -> Vec<Row<'_>> {
+        let mut rows = self.rows();
+
+        if let Features::Total { .. } = self {
+            options.sort(&mut rows, Row::sort_key);
+
+            return rows;
         }
 
-        Ok(Features::BySymbol { data })
-    } else {
-        let counts = binary
-            .instruction_counts()
+        let mut functions: Vec<Vec<Row>> = rows
             .into_iter()
-            .map(|((mnemonic, features), count)| {
-                let feature_names: Vec<_> = features
-                    .iter()
-                    .map(|x| Feature(lowercase(x)))
-                    .collect();
+            .chunk_by(|row| row.symbol)
+            .into_iter()
+            .map(|(_, rows)| {
+                let mut rows: Vec<_> = rows.collect();
 
-                (
-                    (Instruction(lowercase(mnemonic)), feature_names),
-                    count,
-                )
-            })
-            .filter(|((_, features), _)| {
-                feature_filter.is_empty()
-                    || features.iter().any(|Feature(name)| {
-                        feature_filter
-                            .iter()
-                            .any(|pattern| pattern.matches(name))
-                    })
+                options.order(&mut rows, Row::sort_key);
+
+                rows
             })
-            .map(|((mnemonic, features), count)| {
-                (
-                    ConcatenatedFeatures(
-                        features
-                            .into_iter()
-                            .map(|Feature(x)| x)
-                            .join(","),
-                    ),
-                    mnemonic,
-                    count,
-                )
-            });
-        let mut data = BTreeMap::new();
+            .collect();
 
-        for (features, mnemonic, count) in counts {
-            data.entry(features)
-                .or_insert_with(BTreeMap::new)
-                .insert(mnemonic, count);
+        options.sort(&mut functions, |rows| SortKey {
+            extension: rows
+                .iter()
+                .map(|row| row.extension.features.0.as_str())
+                .min()
+                .unwrap_or_default(),
+            usage: rows.iter().map(|row| row.usage).sum(),
+        });
+
+        functions.into_iter().flatten().collect()
+    }
+
+    /// Names of the extension sets used, qualified by encoding if
+    /// grouping by encoding was requested.
+    fn extensions(&self) -> BTreeSet<String> {
+        self.extension_usage().into_keys().collect()
+    }
+
+    /// Instructions of each extension set.
+    fn extension_usage(&self) -> BTreeMap<String, Usage> {
+        let mut result = BTreeMap::new();
+
+        for row in self.rows() {
+            *result.entry(row.extension.to_string()).or_default() +=
+                row.usage;
         }
 
-        Ok(Features::Total { data })
+        result
     }
-}
 
-pub fn print_list(features: &Features) -> anyhow::Result<()> {
-    match features {
-        Features::Total { data } => {
-            let feature_names = BTreeSet::from_iter(data.keys().map(
-                |ConcatenatedFeatures(features)| features.clone(),
-            ));
+    /// Instructions of each function, if counted by symbol.
+    fn symbol_usage(&self) -> BTreeMap<&str, Usage> {
+        let mut result = BTreeMap::new();
 
-            for name in feature_names.iter() {
-                println!("{name}");
+        for row in self.rows() {
+            if let Some(symbol) = row.symbol {
+                *result.entry(symbol).or_default() += row.usage;
             }
         }
-        Features::BySymbol { data } => {
-            let mut feature_use = BTreeMap::new();
 
-            for (Symbol(symbol), feature_counts) in data.iter() {
-                for ConcatenatedFeatures(features) in
-                    feature_counts.keys()
-                {
-                    feature_use
-                        .entry(features)
-                        .or_insert_with(BTreeSet::new)
-                        .insert(symbol);
-                }
+        result
+    }
+
+    /// Counts by extension set and instruction (and function), along
+    /// with sizes and shares if requested.
+    fn to_json(&self, options: &PrintOptions) -> serde_json::Value {
+        let total = self.totals().usage.count;
+        let value = |row: &Row| {
+            if !options.sizes && !options.summary {
+                return serde_json::json!(row.usage.count);
             }
 
-            for (feature, symbols) in feature_use.iter() {
-                println!("Functions that use {feature}:");
+            let mut result =
+                serde_json::json!({ "count": row.usage.count });
 
-                for symbol in symbols.iter() {
-                    println!("- {symbol}");
-                }
+            if options.sizes {
+                result["bytes"] = serde_json::json!(row.usage.bytes);
+            }
 
-                println!();
+            if options.summary {
+                result["share"] =
+                    serde_json::json!(ratio(row.usage.count, total));
+
+                if let Some(symbol) = row.symbol {
+                    result["function_share"] =
+                        serde_json::json!(ratio(
+                            row.usage.count,
+                            self.symbol_total(symbol).count
+                        ));
+                }
             }
+
+            result
+        };
+        let mut data: BTreeMap<
+            &str,
+            BTreeMap<String, BTreeMap<&str, _>>,
+        > = BTreeMap::new();
+
+        for row in self.sorted_rows(options) {
+            data.entry(row.symbol.unwrap_or_default())
+                .or_default()
+                .entry(row.extension.to_string())
+                .or_default()
+                .insert(row.mnemonic, value(&row));
+        }
+
+        match self {
+            Features::Total { .. } => serde_json::json!(data
+                .into_values()
+                .next()
+                .unwrap_or_default()),
+            Features::BySymbol { .. } => serde_json::json!(data),
         }
     }
 
-    Ok(())
+    /// Total size of the code and sizes by extension set and function.
+    fn sizes_json(&self) -> serde_json::Value {
+        let mut result = serde_json::json!({
+            "total": self.size(),
+            "extensions": bytes(self.extension_usage()),
+        });
+
+        if let Features::BySymbol { .. } = self {
+            result["symbols"] =
+                serde_json::json!(bytes(self.symbol_usage()));
+        }
+
+        result
+    }
+
+    /// One record per function (if counted by symbol), extension set,
+    /// and opcode, for versioned JSON output.
+    fn records(
+        &self,
+        options: &PrintOptions,
+    ) -> Vec<serde_json::Value> {
+        self.sorted_rows(options)
+            .into_iter()
+            .map(|row| {
+                let ConcatenatedFeatures(features) =
+                    &row.extension.features;
+
+                serde_json::json!({
+                    "symbol": row.symbol,
+                    "features": features.split(',').collect::<Vec<_>>(),
+                    "encoding": row
+                        .extension
+                        .encoding
+                        .as_ref()
+                        .map(|Encoding(x)| x),
+                    "mnemonic": row.mnemonic,
+                    "count": row.usage.count,
+                    "bytes": row.usage.bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Number of instructions of the binary and of each function.
+    fn totals_json(&self) -> serde_json::Value {
+        let mut result = serde_json::json!({
+            "instructions": self.totals().usage.count,
+        });
+
+        if let Features::BySymbol { symbol_totals, .. } = self {
+            result["symbols"] = symbol_totals
+                .iter()
+                .map(|(symbol, usage)| {
+                    (symbol.clone(), serde_json::json!(usage.count))
+                })
+                .collect();
+        }
+
+        result
+    }
 }
 
-pub fn print_table(features: &Features) -> anyhow::Result<()> {
-    match features {
-        Features::Total { data } => {
-            let width_ext = width(
-                "Extension",
-                data.keys(),
-                |ConcatenatedFeatures(features)| features.len(),
-            );
-            let width_opcode =
-                width("Opcode", data.values(), |counts| {
-                    counts
-                        .keys()
-                        .map(|Instruction(mnemonic)| mnemonic.len())
-                        .max()
-                        .unwrap_or_default()
-                });
-            let width_count = width("Count", data.values(), |counts| {
-                counts
-                    .values()
-                    .map(|count| {
-                        usize::try_from(1 + count.ilog10()).expect(
-                            "usize should be at least 32 bits wide",
-                        )
-                    })
-                    .max()
-                    .unwrap_or_default()
-            });
+/// How features are printed.
+#[derive(Default)]
+pub struct PrintOptions {
+    /// Also print code sizes in bytes and as shares of the code.
+    pub sizes: bool,
+    pub sort_by: SortBy,
+    /// Reverse the order given by `sort_by`.
+    pub reverse: bool,
+    /// Only print this many items of each list or table.
+    pub top: Option<usize>,
+    /// Also print totals, the share of each row in them, and the share
+    /// of SIMD instructions.
+    pub summary: bool,
+    /// Print JSON in the unversioned shape of earlier releases.
+    pub legacy_json: bool,
+    /// Options that the output was produced with, recorded in
+    /// versioned JSON output.
+    pub settings: serde_json::Value,
+}
 
-            println!(
-                "{0:^3$} {1:^4$} {2:^5$}",
-                "Extension",
-                "Opcode",
-                "Count",
-                width_ext,
-                width_opcode,
-                width_count
-            );
-            println!(
-                "{} {} {}",
-                "-".repeat(width_ext),
-                "-".repeat(width_opcode),
-                "-".repeat(width_count)
-            );
+/// What an item of a list or a table is sorted by, besides its name.
+struct SortKey<'a> {
+    extension: &'a str,
+    usage: Usage,
+}
 
-            for (ConcatenatedFeatures(features), counts) in data.iter()
-            {
-                for (Instruction(mnemonic), count) in counts.iter() {
-                    println!(
-                        "{0:3$} {1:4$} {2:5$}",
-                        features,
-                        mnemonic,
-                        count,
-                        width_ext,
-                        width_opcode,
-                        width_count
-                    );
-                }
+impl PrintOptions {
+    /// Sorts `items`, which are in name order, as requested and keeps
+    /// the first `top` of them.  Items that compare equal keep their
+    /// order.
+    fn sort<T>(&self, items: &mut Vec<T>, key: impl Fn(&T) -> SortKey) {
+        self.order(items, key);
+
+        if self.reverse {
+            items.reverse();
+        }
+
+        if let Some(top) = self.top {
+            items.truncate(top);
+        }
+    }
+
+    /// Sorts `items` by `sort_by` alone, without reversing or
+    /// truncating them.
+    fn order<T>(&self, items: &mut [T], key: impl Fn(&T) -> SortKey) {
+        match self.sort_by {
+            SortBy::Name => {}
+            SortBy::Count => items
+                .sort_by_key(|x| std::cmp::Reverse(key(x).usage.count)),
+            SortBy::Bytes => items
+                .sort_by_key(|x| std::cmp::Reverse(key(x).usage.bytes)),
+            SortBy::Feature => items
+                .sort_by(|a, b| key(a).extension.cmp(key(b).extension)),
+        }
+    }
+}
+
+/// Features of several binaries, e.g., of a program and the shared
+/// libraries that it depends on, in the order in which they were
+/// added.
+#[derive(Default)]
+pub struct Report {
+    files: Vec<(String, Features, Metadata)>,
+    /// Packages and image layers, and the indices of the entries of
+    /// `files` that were extracted from them.
+    containers: Vec<(ContainerKind, String, Vec<usize>)>,
+}
+
+/// What files in a report can be extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    Package,
+    /// Layer of a container image.
+    Layer,
+}
+
+impl ContainerKind {
+    fn title(self) -> &'static str {
+        match self {
+            ContainerKind::Package => "Package",
+            ContainerKind::Layer => "Layer",
+        }
+    }
+
+    fn json_key(self) -> &'static str {
+        match self {
+            ContainerKind::Package => "packages",
+            ContainerKind::Layer => "layers",
+        }
+    }
+}
+
+impl Report {
+    pub fn add(
+        &mut self,
+        path: String,
+        features: Features,
+        metadata: Metadata,
+    ) {
+        self.files.push((path, features, metadata));
+    }
+
+    /// Adds a file that was extracted from `container`.  The file is
+    /// listed as `CONTAINER:PATH`.
+    pub fn add_to_container(
+        &mut self,
+        kind: ContainerKind,
+        container: &str,
+        path: &str,
+        features: Features,
+        metadata: Metadata,
+    ) {
+        let index = self.files.len();
+
+        self.add(format!("{container}:{path}"), features, metadata);
+
+        match self.containers.last_mut() {
+            Some((_, name, indices)) if name == container => {
+                indices.push(index)
             }
+            _ => self.containers.push((
+                kind,
+                container.to_string(),
+                vec![index],
+            )),
         }
-        Features::BySymbol { data } => {
-            let width_sym =
-                width("Function", data.keys(), |Symbol(x)| x.len());
-            let width_ext =
-                width("Extension", data.values(), |counts| {
-                    counts
-                        .keys()
-                        .map(|ConcatenatedFeatures(features)| {
-                            features.len()
-                        })
-                        .max()
-                        .unwrap_or_default()
-                });
-            let width_opcode =
-                width("Opcode", data.values(), |counts| {
-                    counts
-                        .values()
-                        .map(|counts| {
-                            counts
-                                .keys()
-                                .map(|Instruction(mnemonic)| {
-                                    mnemonic.len()
-                                })
-                                .max()
-                                .unwrap_or_default()
-                        })
-                        .max()
-                        .unwrap_or_default()
-                });
-            let width_count = width("Count", data.values(), |counts| {
-                counts
-                    .values()
-                    .map(|counts| {
-                        counts.values().map(|count| {
-                            usize::try_from(1 + count.ilog10()).expect(
-                                "usize should be at least 32 bits wide",
-                            )
-                        }).max().unwrap_or_default()
-                    })
-                    .max()
-                    .unwrap_or_default()
-            });
+    }
 
-            println!(
-                "{0:^4$} {1:^5$} {2:^6$} {3:^7$}",
-                "Function",
-                "Extension",
-                "Opcode",
-                "Count",
-                width_sym,
-                width_ext,
-                width_opcode,
-                width_count
-            );
-            println!(
-                "{} {} {} {}",
-                "-".repeat(width_sym),
-                "-".repeat(width_ext),
-                "-".repeat(width_opcode),
-                "-".repeat(width_count)
-            );
+    /// Paths of the files that use each extension set, along with its
+    /// instructions in each file.
+    fn summary(&self) -> BTreeMap<String, Vec<(&str, Usage)>> {
+        let mut result = BTreeMap::new();
 
-            for (Symbol(symbol), feature_counts) in data.iter() {
-                for (ConcatenatedFeatures(features), counts) in
-                    feature_counts.iter()
-                {
-                    for (Instruction(mnemonic), count) in counts.iter()
-                    {
-                        println!(
-                            "{0:4$} {1:5$} {2:6$} {3:7$}",
-                            symbol,
-                            features,
-                            mnemonic,
-                            count,
-                            width_sym,
-                            width_ext,
-                            width_opcode,
-                            width_count
-                        );
+        for (path, features, _) in self.files.iter() {
+            for (extension, usage) in features.extension_usage() {
+                result
+                    .entry(extension)
+                    .or_insert_with(Vec::new)
+                    .push((path.as_str(), usage));
+            }
+        }
+
+        result
+    }
+
+    /// Number of files in each container of the given kind that use
+    /// each extension set.
+    fn container_summary(
+        &self,
+        kind: ContainerKind,
+    ) -> Vec<(&str, BTreeMap<String, usize>)> {
+        self.containers
+            .iter()
+            .filter(|(x, _, _)| *x == kind)
+            .map(|(_, container, indices)| {
+                let mut counts = BTreeMap::new();
+
+                for &index in indices.iter() {
+                    let (_, features, _) = &self.files[index];
+
+                    for extension in features.extensions() {
+                        *counts.entry(extension).or_default() += 1;
                     }
                 }
-            }
+
+                (container.as_str(), counts)
+            })
+            .collect()
+    }
+}
+
+/// Settings the binary was analyzed with, recorded in the output when
+/// they differ from the defaults.  The format, digest, and bitness of
+/// the binary are only recorded in versioned JSON output.
+#[derive(Serialize)]
+pub struct Metadata {
+    #[serde(skip)]
+    format: &'static str,
+    #[serde(skip)]
+    sha256: String,
+    #[serde(skip)]
+    bitness: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    decoder_options: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    regions: Vec<RegionInfo>,
+}
+
+#[derive(Serialize)]
+struct RegionInfo {
+    name: String,
+    address: usize,
+    size: usize,
+    bitness: u32,
+}
+
+impl Metadata {
+    pub fn new(binary: &Binary) -> Self {
+        let regions = if binary.has_mixed_bitness() {
+            binary
+                .regions()
+                .into_iter()
+                .map(|region| RegionInfo {
+                    name: region.name.to_string(),
+                    address: region.address,
+                    size: region.size(),
+                    bitness: region.bitness,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            format: binary.format(),
+            sha256: binary.sha256().to_string(),
+            bitness: binary.bitness(),
+            decoder_options: binary.decoder_option_names(),
+            regions,
         }
     }
 
-    Ok(())
+    fn is_empty(&self) -> bool {
+        self.decoder_options.is_empty() && self.regions.is_empty()
+    }
 }
 
-pub fn print_json(features: &Features) -> anyhow::Result<()> {
-    let output = match features {
-        Features::Total { data } => serde_json::to_string(data)?,
-        Features::BySymbol { data } => serde_json::to_string(data)?,
-    };
+/// Number of samples of a profile by extension set, out of the samples
+/// that hit the binary and of all samples in the profile.
+pub struct SampleShares {
+    data: BTreeMap<String, usize>,
+    in_binary: usize,
+    total: usize,
+}
 
-    println!("{output}");
+impl SampleShares {
+    /// Extension sets in decreasing order of samples.
+    fn sorted(&self) -> Vec<(&String, usize)> {
+        let mut result: Vec<_> = self
+            .data
+            .iter()
+            .map(|(name, &count)| (name, count))
+            .collect();
 
-    Ok(())
+        result.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+        result
+    }
+
+    fn share(&self, count: usize) -> f64 {
+        ratio(count, self.in_binary)
+    }
+}
+
+pub enum VectorSummary {
+    Total { data: VectorUsage },
+    BySymbol { data: BTreeMap<Symbol, VectorUsage> },
+}
+
+/// Column title, description, and value of each field of
+/// `VectorUsage`.
+#[allow(clippy::type_complexity)]
+const VECTOR_USAGE_FIELDS: [(&str, &str, fn(&VectorUsage) -> usize);
+    7] = [
+    ("128-bit", "128-bit vectors", |x| x.width_128),
+    ("256-bit", "256-bit vectors", |x| x.width_256),
+    ("512-bit", "512-bit vectors", |x| x.width_512),
+    ("Masking", "opmask registers", |x| x.masking),
+    ("Zeroing", "zeroing-masking", |x| x.zeroing_masking),
+    ("Broadcast", "embedded broadcast", |x| x.broadcast),
+    ("Rounding", "static rounding or SAE", |x| x.rounding),
+];
+
+pub fn lowercase(value: impl Debug) -> String {
+    format!("{value:?}").to_ascii_lowercase()
+}
+
+fn width<T>(
+    title: &str,
+    items: impl Iterator<Item = T>,
+    length: impl Fn(T) -> usize,
+) -> usize {
+    let min_width = title.len();
+
+    items
+        .map(length)
+        .max()
+        .filter(|&x| x > min_width)
+        .unwrap_or(min_width)
+}
+
+/// Bytes of each item of `usage`.
+fn bytes<K: Ord>(usage: BTreeMap<K, Usage>) -> BTreeMap<K, usize> {
+    usage
+        .into_iter()
+        .map(|(key, usage)| (key, usage.bytes))
+        .collect()
+}
+
+/// `part` as a fraction of `whole`, or 0 if `whole` is 0.
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// `part` as a percentage of `whole`, e.g., `12.3%`.
+fn percentage(part: usize, whole: usize) -> String {
+    format!("{:.1}%", 100.0 * ratio(part, whole))
+}
+
+/// Conventional spelling of an extension, e.g., `AVX-512F` for
+/// `avx512f` or `SSE4.1` for `sse4_1`.
+fn display_name(name: &str) -> String {
+    name.to_ascii_uppercase()
+        .replace("AVX512", "AVX-512")
+        .replace("SSE4_", "SSE4.")
+        .replace("D3NOW", "3DNOW")
+}
+
+fn extension(
+    features: Vec<Feature>,
+    encoding: Option<EncodingKind>,
+) -> Extension {
+    Extension {
+        features: ConcatenatedFeatures(
+            features.into_iter().map(|Feature(x)| x).join(","),
+        ),
+        encoding: encoding.map(|x| Encoding(lowercase(x))),
+    }
+}
+
+fn symbol_name(
+    raw_symbol_name: &str,
+    show_symbols: ShowSymbols,
+) -> Symbol {
+    match show_symbols {
+        ShowSymbols::No | ShowSymbols::Raw => {
+            Symbol(raw_symbol_name.to_string())
+        }
+        ShowSymbols::Demangled => Symbol(
+            Name::from(raw_symbol_name)
+                .try_demangle(DemangleOptions::complete())
+                .to_string(),
+        ),
+    }
+}
+
+fn symbol_matches(
+    symbol_name: &str,
+    raw_symbol_filter: &[WildMatch],
+    demangled_symbol_filter: &[WildMatch],
+) -> bool {
+    // Versioned names such as `memcpy@@GLIBC_2.14` also match `memcpy`.
+    //
+    let unversioned_name =
+        symbol_name.split_once('@').map_or(symbol_name, |(x, _)| x);
+
+    (raw_symbol_filter.is_empty() && demangled_symbol_filter.is_empty())
+        || raw_symbol_filter.iter().any(|pattern| {
+            pattern.matches(symbol_name)
+                || pattern.matches(unversioned_name)
+        })
+        || demangled_symbol_filter.iter().any(|pattern| {
+            let demangled = Name::from(symbol_name)
+                .try_demangle(DemangleOptions::complete())
+                .to_string();
+            pattern.matches(&demangled)
+        })
+}
+
+/// Criteria for including a function in the output besides its
+/// extension sets.
+pub struct SymbolFilter<'a> {
+    pub raw: &'a [WildMatch],
+    pub demangled: &'a [WildMatch],
+    /// Only include functions that use zmm registers.
+    pub zmm_only: bool,
+    /// Only include functions exported in `.dynsym`.
+    pub exported_only: bool,
+}
+
+impl SymbolFilter<'_> {
+    /// Whether instructions have to be counted by symbol to apply the
+    /// filter.
+    fn is_by_symbol(&self) -> bool {
+        !self.raw.is_empty() || self.zmm_only || self.exported_only
+    }
+
+    fn matches(&self, symbol_name: &str) -> bool {
+        symbol_matches(symbol_name, self.raw, self.demangled)
+    }
+}
+
+/// Raw names of the symbols that use at least one zmm register.
+fn zmm_symbols(binary: &Binary) -> anyhow::Result<HashSet<&str>> {
+    Ok(binary
+        .vector_usage_by_symbol()?
+        .into_iter()
+        .filter(|(_, usage)| usage.width_512 > 0)
+        .map(|(name, _)| name)
+        .collect())
+}
+
+pub fn get_features(
+    binary: &Binary,
+    feature_filter: &[WildMatch],
+    symbol_filter: &SymbolFilter,
+    show_symbols: ShowSymbols,
+    by_encoding: bool,
+    by_family: bool,
+    samples: Option<&BTreeMap<usize, usize>>,
+) -> anyhow::Result<Features> {
+    let feature_names = |features: &[CpuidFeature]| -> Vec<Feature> {
+        features.iter().map(|x| Feature(lowercase(x))).collect()
+    };
+    // Patterns can also name a family, e.g., `sse` for SSE4.1 or
+    // `avx512` for every AVX-512 subfamily.
+    //
+    let matches_feature_filter = |features: &[Feature]| {
+        feature_filter.is_empty()
+            || features.iter().any(|Feature(name)| {
+                feature_filter.iter().any(|pattern| {
+                    pattern.matches(name)
+                        || family::families(name)
+                            .into_iter()
+                            .any(|family| pattern.matches(family))
+                })
+            })
+    };
+    let to_extension = |features: Vec<Feature>, encoding| {
+        if by_family {
+            let families = features
+                .iter()
+                .map(|Feature(name)| family::family(name))
+                .unique()
+                .sorted()
+                .map(|name| Feature(name.to_string()))
+                .collect();
+
+            extension(families, encoding)
+        } else {
+            extension(features, encoding)
+        }
+    };
+    let mut totals = Totals::default();
+
+    if show_symbols != ShowSymbols::No || symbol_filter.is_by_symbol() {
+        let zmm_symbols = if symbol_filter.zmm_only {
+            Some(zmm_symbols(binary)?)
+        } else {
+            None
+        };
+        let mut data = BTreeMap::new();
+        let mut symbol_totals = BTreeMap::new();
+
+        for ((raw_symbol_name, mnemonic, features, encoding), usage) in
+            binary.instruction_counts_by_symbol(by_encoding, samples)?
+        {
+            if !zmm_symbols
+                .as_ref()
+                .is_none_or(|x| x.contains(raw_symbol_name))
+                || (symbol_filter.exported_only
+                    && !binary.is_exported(raw_symbol_name))
+            {
+                continue;
+            }
+
+            let symbol = symbol_name(raw_symbol_name, show_symbols);
+            let Symbol(name) = &symbol;
+
+            if !symbol_filter.matches(name) {
+                continue;
+            }
+
+            let features = feature_names(features);
+
+            totals.add(&features, usage);
+            *symbol_totals.entry(name.clone()).or_default() += usage;
+
+            if !matches_feature_filter(&features) {
+                continue;
+            }
+
+            // Functions can share a name, e.g., after demangling.
+            //
+            *data
+                .entry(symbol)
+                .or_insert_with(BTreeMap::new)
+                .entry(to_extension(features, encoding))
+                .or_insert_with(BTreeMap::new)
+                .entry(Instruction(lowercase(mnemonic)))
+                .or_default() += usage;
+        }
+
+        symbol_totals
+            .retain(|name, _| data.contains_key(&Symbol(name.clone())));
+
+        Ok(Features::BySymbol {
+            data,
+            totals,
+            symbol_totals,
+        })
+    } else {
+        let mut data = BTreeMap::new();
+
+        for ((mnemonic, features, encoding), usage) in
+            binary.instruction_counts(by_encoding, samples)
+        {
+            let features = feature_names(features);
+
+            totals.add(&features, usage);
+
+            if !matches_feature_filter(&features) {
+                continue;
+            }
+
+            *data
+                .entry(to_extension(features, encoding))
+                .or_insert_with(BTreeMap::new)
+                .entry(Instruction(lowercase(mnemonic)))
+                .or_default() += usage;
+        }
+
+        Ok(Features::Total { data, totals })
+    }
+}
+
+/// Adds up the samples in `features`, which were counted with the
+/// samples of a profile, by extension set.
+pub fn get_sample_shares(
+    features: &Features,
+    in_binary: usize,
+    total: usize,
+) -> SampleShares {
+    let mut data = BTreeMap::new();
+
+    for row in features.rows() {
+        *data.entry(row.extension.to_string()).or_default() +=
+            row.usage.count;
+    }
+
+    SampleShares {
+        data,
+        in_binary,
+        total,
+    }
+}
+
+pub fn get_vector_summary(
+    binary: &Binary,
+    symbol_filter: &SymbolFilter,
+    show_symbols: ShowSymbols,
+) -> anyhow::Result<VectorSummary> {
+    if show_symbols != ShowSymbols::No || symbol_filter.is_by_symbol() {
+        let mut data = BTreeMap::new();
+
+        for (raw_symbol_name, usage) in
+            binary.vector_usage_by_symbol()?
+        {
+            if usage == VectorUsage::default()
+                || (symbol_filter.zmm_only && usage.width_512 == 0)
+                || (symbol_filter.exported_only
+                    && !binary.is_exported(raw_symbol_name))
+            {
+                continue;
+            }
+
+            let symbol_name =
+                symbol_name(raw_symbol_name, show_symbols);
+            let Symbol(name) = &symbol_name;
+
+            if !symbol_filter.matches(name) {
+                continue;
+            }
+
+            data.insert(symbol_name, usage);
+        }
+
+        Ok(VectorSummary::BySymbol { data })
+    } else {
+        Ok(VectorSummary::Total {
+            data: binary.vector_usage(),
+        })
+    }
+}
+
+/// Number of SIMD instructions in each kernel function that does not
+/// call `kernel_fpu_begin`.
+pub fn get_unguarded_simd(
+    binary: &Binary,
+    symbol_filter: &SymbolFilter,
+    show_symbols: ShowSymbols,
+) -> anyhow::Result<BTreeMap<Symbol, usize>> {
+    let mut result = BTreeMap::new();
+
+    for (raw_symbol_name, count) in binary.unguarded_simd()? {
+        if symbol_filter.exported_only
+            && !binary.is_exported(raw_symbol_name)
+        {
+            continue;
+        }
+
+        let symbol_name = symbol_name(raw_symbol_name, show_symbols);
+        let Symbol(name) = &symbol_name;
+
+        if symbol_filter.matches(name) {
+            result.insert(symbol_name, count);
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn print_list(
+    features: &Features,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    match features {
+        Features::Total { .. } => {
+            let mut extensions: Vec<_> =
+                features.extension_usage().into_iter().collect();
+
+            options.sort(&mut extensions, |(name, usage)| SortKey {
+                extension: name,
+                usage: *usage,
+            });
+
+            for (name, usage) in extensions.iter() {
+                if options.sizes {
+                    println!(
+                        "{name}: {} bytes ({} of the code)",
+                        usage.bytes,
+                        percentage(usage.bytes, features.size())
+                    );
+                } else {
+                    println!("{name}");
+                }
+            }
+        }
+        Features::BySymbol { .. } => {
+            let mut feature_use = BTreeMap::new();
+
+            for row in features.rows() {
+                *feature_use
+                    .entry(row.extension)
+                    .or_insert_with(BTreeMap::new)
+                    .entry(row.symbol.unwrap_or_default())
+                    .or_default() += row.usage;
+            }
+
+            let mut feature_use: Vec<_> = feature_use
+                .into_iter()
+                .map(|(feature, symbols)| {
+                    let usage = symbols.values().copied().sum();
+                    let mut symbols: Vec<_> =
+                        symbols.into_iter().collect();
+
+                    options.sort(&mut symbols, |&(_, usage)| SortKey {
+                        extension: "",
+                        usage,
+                    });
+
+                    (feature, usage, symbols)
+                })
+                .collect();
+
+            options.sort(&mut feature_use, |&(feature, usage, _)| {
+                SortKey {
+                    extension: &feature.features.0,
+                    usage,
+                }
+            });
+
+            for (feature, _, symbols) in feature_use.iter() {
+                println!("Functions that use {feature}:");
+
+                for (symbol, usage) in symbols.iter() {
+                    if options.sizes {
+                        println!("- {symbol} ({} bytes)", usage.bytes);
+                    } else {
+                        println!("- {symbol}");
+                    }
+                }
+
+                println!();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_table(
+    features: &Features,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let (headers, rows) =
+        table_rows(features, features.by_encoding(), options);
+
+    print_rows(&headers, &rows);
+
+    if options.sizes {
+        print_size_tables(features, options);
+    }
+
+    if options.summary {
+        print_totals(features, options);
+    }
+
+    Ok(())
+}
+
+/// Prints the size of each extension set and function.
+fn print_size_tables(features: &Features, options: &PrintOptions) {
+    println!();
+    print_rows(
+        &["Extension", "Bytes", "% of code"],
+        &size_rows(
+            features.extension_usage().into_iter().collect(),
+            features.size(),
+            options,
+        ),
+    );
+
+    if let Features::BySymbol { .. } = features {
+        println!();
+        print_rows(
+            &["Function", "Bytes", "% of code"],
+            &size_rows(
+                features
+                    .symbol_usage()
+                    .into_iter()
+                    .map(|(name, usage)| (name.to_string(), usage))
+                    .collect(),
+                features.size(),
+                options,
+            ),
+        );
+    }
+}
+
+/// Bytes of each item of `sizes` and their share of `total` bytes.
+fn size_rows(
+    mut sizes: Vec<(String, Usage)>,
+    total: usize,
+    options: &PrintOptions,
+) -> Vec<Vec<Cell>> {
+    options.sort(&mut sizes, |(name, usage)| SortKey {
+        extension: name,
+        usage: *usage,
+    });
+
+    sizes
+        .into_iter()
+        .map(|(name, usage)| {
+            vec![
+                Cell::Text(name),
+                Cell::from(usage.bytes),
+                Cell::Number(percentage(usage.bytes, total)),
+            ]
+        })
+        .collect()
+}
+
+/// Prints the number of instructions of each function and of the
+/// binary, and the share of SIMD instructions.
+fn print_totals(features: &Features, options: &PrintOptions) {
+    let totals = features.totals();
+
+    if let Features::BySymbol { symbol_totals, .. } = features {
+        let mut symbol_totals: Vec<_> = symbol_totals.iter().collect();
+
+        options.sort(&mut symbol_totals, |&(_, &usage)| SortKey {
+            extension: "",
+            usage,
+        });
+
+        let rows: Vec<_> = symbol_totals
+            .into_iter()
+            .map(|(symbol, usage)| {
+                vec![
+                    Cell::Text(symbol.clone()),
+                    Cell::from(usage.count),
+                    Cell::Number(percentage(
+                        usage.count,
+                        totals.usage.count,
+                    )),
+                ]
+            })
+            .collect();
+
+        println!();
+        print_rows(&["Function", "Instructions", "% of total"], &rows);
+    }
+
+    println!();
+    println!("Total: {} instructions", totals.usage.count);
+    println!("{}", totals.summary());
+
+    let implied = totals.implied();
+
+    if !implied.is_empty() {
+        println!("Implied extensions: {}", implied.iter().join(", "));
+    }
+}
+
+fn table_rows(
+    features: &Features,
+    by_encoding: bool,
+    options: &PrintOptions,
+) -> (Vec<&'static str>, Vec<Vec<Cell>>) {
+    let mut headers = Vec::new();
+
+    if let Features::BySymbol { .. } = features {
+        headers.push("Function");
+    }
+
+    headers.push("Extension");
+
+    if by_encoding {
+        headers.push("Encoding");
+    }
+
+    headers.extend(["Opcode", "Count"]);
+
+    if options.sizes {
+        headers.extend(["Bytes", "% of code"]);
+    }
+
+    if options.summary {
+        if let Features::BySymbol { .. } = features {
+            headers.push("% of function");
+        }
+
+        headers.push("% of total");
+    }
+
+    let rows = features
+        .sorted_rows(options)
+        .into_iter()
+        .map(|row| {
+            let mut cells: Vec<_> = row
+                .symbol
+                .map(|x| Cell::Text(x.to_string()))
+                .into_iter()
+                .collect();
+
+            cells.extend(extension_cells(row.extension, by_encoding));
+            cells.push(Cell::Text(row.mnemonic.to_string()));
+            cells.push(Cell::from(row.usage.count));
+
+            if options.sizes {
+                cells.push(Cell::from(row.usage.bytes));
+                cells.push(Cell::Number(percentage(
+                    row.usage.bytes,
+                    features.size(),
+                )));
+            }
+
+            if options.summary {
+                if let Some(symbol) = row.symbol {
+                    cells.push(Cell::Number(percentage(
+                        row.usage.count,
+                        features.symbol_total(symbol).count,
+                    )));
+                }
+
+                cells.push(Cell::Number(percentage(
+                    row.usage.count,
+                    features.totals().usage.count,
+                )));
+            }
+
+            cells
+        })
+        .collect();
+
+    (headers, rows)
+}
+
+/// Prints one line per function (if counted by symbol), extension
+/// set, and opcode, with fields separated by `separator`.
+pub fn print_delimited(
+    path: &str,
+    features: &Features,
+    separator: char,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let by_encoding = features.by_encoding();
+
+    println!("{}", delimited_header(by_encoding, separator, options));
+
+    for line in
+        delimited_rows(path, features, by_encoding, separator, options)
+    {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn delimited_header(
+    by_encoding: bool,
+    separator: char,
+    options: &PrintOptions,
+) -> String {
+    let mut headers = vec!["file", "symbol", "extension"];
+
+    if by_encoding {
+        headers.push("encoding");
+    }
+
+    headers.extend(["mnemonic", "count"]);
+
+    if options.sizes {
+        headers.push("bytes");
+    }
+
+    headers.join(&separator.to_string())
+}
+
+fn delimited_rows(
+    path: &str,
+    features: &Features,
+    by_encoding: bool,
+    separator: char,
+    options: &PrintOptions,
+) -> Vec<String> {
+    let mut result = Vec::new();
+
+    for row in features.sorted_rows(options) {
+        let ConcatenatedFeatures(extension) = &row.extension.features;
+        let mut fields = vec![
+            path.to_string(),
+            row.symbol.unwrap_or_default().to_string(),
+            extension.clone(),
+        ];
+
+        if by_encoding {
+            fields.push(
+                row.extension
+                    .encoding
+                    .as_ref()
+                    .map(|Encoding(x)| x.clone())
+                    .unwrap_or_default(),
+            );
+        }
+
+        fields.push(row.mnemonic.to_string());
+        fields.push(row.usage.count.to_string());
+
+        if options.sizes {
+            fields.push(row.usage.bytes.to_string());
+        }
+
+        result.push(
+            fields
+                .iter()
+                .map(|x| quote(x, separator))
+                .join(&separator.to_string()),
+        );
+    }
+
+    result
+}
+
+pub fn print_matrix(
+    features: &Features,
+    format: MatrixFormat,
+    checkmarks: bool,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let rows = matrix_rows(features)
+        .into_iter()
+        .map(|(symbol, usage)| (vec![symbol.to_string()], usage))
+        .collect();
+
+    print_pivot(&["Function"], rows, format, checkmarks, options);
+
+    Ok(())
+}
+
+/// Instructions of each function by extension set.
+fn matrix_rows(
+    features: &Features,
+) -> BTreeMap<&str, BTreeMap<String, Usage>> {
+    let mut result: BTreeMap<&str, BTreeMap<_, _>> = BTreeMap::new();
+
+    for row in features.rows() {
+        if let Some(symbol) = row.symbol {
+            *result
+                .entry(symbol)
+                .or_default()
+                .entry(row.extension.to_string())
+                .or_default() += row.usage;
+        }
+    }
+
+    result
+}
+
+/// Prints one row per item of `rows`, labeled by the columns in
+/// `labels`, and one column per extension set.  Extension sets are
+/// ordered by family, e.g., SSE before AVX before AVX-512.
+fn print_pivot(
+    labels: &[&str],
+    rows: Vec<(Vec<String>, BTreeMap<String, Usage>)>,
+    format: MatrixFormat,
+    checkmarks: bool,
+    options: &PrintOptions,
+) {
+    let (headers, table) =
+        pivot(labels, rows, format, checkmarks, options);
+    let lines = match format {
+        MatrixFormat::Table => {
+            let headers: Vec<&str> =
+                headers.iter().map(String::as_str).collect();
+
+            return print_rows(&headers, &table);
+        }
+        MatrixFormat::Csv => csv_lines(&headers, &table),
+        MatrixFormat::Markdown => {
+            markdown_lines(labels.len(), &headers, &table)
+        }
+    };
+
+    for line in lines {
+        println!("{line}");
+    }
+}
+
+/// Headers and cells of the matrix printed by `print_pivot`.
+fn pivot(
+    labels: &[&str],
+    mut rows: Vec<(Vec<String>, BTreeMap<String, Usage>)>,
+    format: MatrixFormat,
+    checkmarks: bool,
+    options: &PrintOptions,
+) -> (Vec<String>, Vec<Vec<Cell>>) {
+    options.sort(&mut rows, |(_, usage)| SortKey {
+        extension: "",
+        usage: usage.values().copied().sum(),
+    });
+
+    let columns: Vec<&String> = rows
+        .iter()
+        .flat_map(|(_, usage)| usage.keys())
+        .unique()
+        .sorted_by_key(|&name| {
+            let feature = name.split([',', ' ']).next().unwrap_or(name);
+
+            (family::rank(feature), name)
+        })
+        .collect();
+    let cell = |usage: Option<&Usage>| match usage {
+        Some(_) if checkmarks => "✓".to_string(),
+        Some(usage) => usage.count.to_string(),
+        None if format == MatrixFormat::Csv && !checkmarks => {
+            "0".to_string()
+        }
+        None => String::new(),
+    };
+    let headers = labels
+        .iter()
+        .map(ToString::to_string)
+        .chain(columns.iter().map(|&x| x.clone()))
+        .collect();
+    let table = rows
+        .iter()
+        .map(|(label, usage)| {
+            label
+                .iter()
+                .map(|x| Cell::Text(x.clone()))
+                .chain(columns.iter().map(|&column| {
+                    Cell::Number(cell(usage.get(column)))
+                }))
+                .collect()
+        })
+        .collect();
+
+    (headers, table)
+}
+
+fn csv_lines(headers: &[String], table: &[Vec<Cell>]) -> Vec<String> {
+    let mut result =
+        vec![headers.iter().map(|x| quote(x, ',')).join(",")];
+
+    for row in table.iter() {
+        result
+            .push(row.iter().map(|x| quote(x.as_str(), ',')).join(","));
+    }
+
+    result
+}
+
+/// Lines of a Markdown table whose first `labels` columns are
+/// left-aligned and the others right-aligned.
+fn markdown_lines(
+    labels: usize,
+    headers: &[String],
+    table: &[Vec<Cell>],
+) -> Vec<String> {
+    let markdown = |x: &str| x.replace('|', "\\|").replace('\n', " ");
+    let mut result = vec![
+        format!(
+            "| {} |",
+            headers.iter().map(|x| markdown(x)).join(" | ")
+        ),
+        format!(
+            "|{}",
+            headers
+                .iter()
+                .enumerate()
+                .map(|(index, _)| if index < labels {
+                    " --- |"
+                } else {
+                    " ---: |"
+                })
+                .join("")
+        ),
+    ];
+
+    for row in table.iter() {
+        result.push(format!(
+            "| {} |",
+            row.iter().map(|x| markdown(x.as_str())).join(" | ")
+        ));
+    }
+
+    result
+}
+
+/// `field` as a field of a CSV or TSV line, in quotes if it contains
+/// the separator, a quote, or a line break.
+fn quote(field: &str, separator: char) -> String {
+    if field.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn print_vector_summary_list(
+    summary: &VectorSummary,
+) -> anyhow::Result<()> {
+    match summary {
+        VectorSummary::Total { data } => {
+            for (_, description, value) in VECTOR_USAGE_FIELDS {
+                if value(data) > 0 {
+                    println!("{description}");
+                }
+            }
+        }
+        VectorSummary::BySymbol { data } => {
+            for (_, description, value) in VECTOR_USAGE_FIELDS {
+                let symbols: Vec<_> = data
+                    .iter()
+                    .filter(|(_, usage)| value(usage) > 0)
+                    .map(|(Symbol(symbol), _)| symbol)
+                    .collect();
+
+                if symbols.is_empty() {
+                    continue;
+                }
+
+                println!("Functions that use {description}:");
+
+                for symbol in symbols.iter() {
+                    println!("- {symbol}");
+                }
+
+                println!();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_vector_summary_table(
+    summary: &VectorSummary,
+) -> anyhow::Result<()> {
+    let usage_cells = |usage: &VectorUsage| -> Vec<Cell> {
+        VECTOR_USAGE_FIELDS
+            .iter()
+            .map(|(_, _, value)| Cell::from(value(usage)))
+            .collect()
+    };
+    let usage_headers =
+        VECTOR_USAGE_FIELDS.iter().map(|&(title, _, _)| title);
+
+    match summary {
+        VectorSummary::Total { data } => {
+            let headers: Vec<_> = usage_headers.collect();
+
+            print_rows(&headers, &[usage_cells(data)]);
+        }
+        VectorSummary::BySymbol { data } => {
+            let headers: Vec<_> =
+                ["Function"].into_iter().chain(usage_headers).collect();
+            let rows: Vec<Vec<_>> = data
+                .iter()
+                .map(|(Symbol(symbol), usage)| {
+                    [Cell::Text(symbol.clone())]
+                        .into_iter()
+                        .chain(usage_cells(usage))
+                        .collect()
+                })
+                .collect();
+
+            print_rows(&headers, &rows);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_unguarded_simd_list(
+    data: &BTreeMap<Symbol, usize>,
+) -> anyhow::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    println!(
+        "Functions that use SIMD instructions without calling \
+         kernel_fpu_begin:"
+    );
+
+    for Symbol(symbol) in data.keys() {
+        println!("- {symbol}");
+    }
+
+    Ok(())
+}
+
+pub fn print_unguarded_simd_table(
+    data: &BTreeMap<Symbol, usize>,
+) -> anyhow::Result<()> {
+    let rows: Vec<Vec<_>> = data
+        .iter()
+        .map(|(Symbol(symbol), count)| {
+            vec![Cell::Text(symbol.clone()), Cell::from(*count)]
+        })
+        .collect();
+
+    print_rows(&["Function", "SIMD instructions"], &rows);
+
+    Ok(())
+}
+
+pub fn print_sample_shares_list(
+    shares: &SampleShares,
+) -> anyhow::Result<()> {
+    for (name, count) in shares.sorted() {
+        println!(
+            "{name}: {} of samples",
+            percentage(count, shares.in_binary)
+        );
+    }
+
+    Ok(())
+}
+
+pub fn print_sample_shares_table(
+    shares: &SampleShares,
+) -> anyhow::Result<()> {
+    let rows: Vec<_> = shares
+        .sorted()
+        .into_iter()
+        .map(|(name, count)| {
+            vec![
+                Cell::Text(name.clone()),
+                Cell::from(count),
+                Cell::Number(percentage(count, shares.in_binary)),
+            ]
+        })
+        .collect();
+
+    print_rows(&["Extension", "Samples", "Share"], &rows);
+    println!();
+    println!(
+        "{} of {} samples are in this binary",
+        shares.in_binary, shares.total
+    );
+
+    Ok(())
+}
+
+/// Prints the bitness used for each region of code if not all code was
+/// decoded with the bitness implied by the binary's architecture.
+pub fn print_regions_table(metadata: &Metadata) -> anyhow::Result<()> {
+    if metadata.regions.is_empty() {
+        return Ok(());
+    }
+
+    let rows: Vec<_> = metadata
+        .regions
+        .iter()
+        .map(|region| {
+            vec![
+                Cell::Text(region.name.clone()),
+                Cell::Number(format!("{:#x}", region.address)),
+                Cell::from(region.size),
+                Cell::from(region.bitness as usize),
+            ]
+        })
+        .collect();
+
+    println!();
+    print_rows(&["Region", "Address", "Size", "Bitness"], &rows);
+
+    Ok(())
+}
+
+/// A table cell.  Numbers are aligned to the right, text to the left.
+enum Cell {
+    Text(String),
+    Number(String),
+}
+
+impl Cell {
+    fn as_str(&self) -> &str {
+        match self {
+            Cell::Text(x) | Cell::Number(x) => x,
+        }
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(value: usize) -> Self {
+        Cell::Number(value.to_string())
+    }
+}
+
+fn extension_cells(
+    extension: &Extension,
+    by_encoding: bool,
+) -> Vec<Cell> {
+    let ConcatenatedFeatures(features) = &extension.features;
+    let mut result = vec![Cell::Text(features.clone())];
+
+    if by_encoding {
+        let encoding = match &extension.encoding {
+            Some(Encoding(x)) => x.clone(),
+            None => String::new(),
+        };
+
+        result.push(Cell::Text(encoding));
+    }
+
+    result
+}
+
+fn print_rows(headers: &[&str], rows: &[Vec<Cell>]) {
+    let widths: Vec<_> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, title)| {
+            width(title, rows.iter(), |row| {
+                row[index].as_str().chars().count()
+            })
+        })
+        .collect();
+
+    println!(
+        "{}",
+        headers
+            .iter()
+            .zip(widths.iter())
+            .map(|(title, width)| format!("{title:^width$}"))
+            .join(" ")
+    );
+    println!(
+        "{}",
+        widths.iter().map(|&width| "-".repeat(width)).join(" ")
+    );
+
+    for row in rows.iter() {
+        println!(
+            "{}",
+            row.iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| match cell {
+                    Cell::Text(x) => format!("{x:width$}"),
+                    Cell::Number(x) => format!("{x:>width$}"),
+                })
+                .join(" ")
+        );
+    }
+}
+
+pub fn print_json(
+    path: &str,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let output = if options.legacy_json {
+        features_json(features, metadata, options)?
+    } else {
+        json_document(
+            vec![file_json(path, features, metadata, options)],
+            Vec::new(),
+            options,
+        )
+    };
+
+    println!("{}", serde_json::to_string(&output)?);
+
+    Ok(())
+}
+
+/// Version of the document printed by `print_json` and
+/// `print_report_json`.  It is incremented when fields are removed or
+/// change their meaning, but not when fields are added.
+const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema of the document, published in `schema/` in the
+/// repository.
+const JSON_SCHEMA_URL: &str = "https://raw.githubusercontent.com/gn0/\
+     lsx86features/main/schema/lsx86features.schema.json";
+
+/// Self-describing document with the tool version, the options used,
+/// and one entry per file.
+fn json_document(
+    files: Vec<serde_json::Value>,
+    containers: Vec<serde_json::Value>,
+    options: &PrintOptions,
+) -> serde_json::Value {
+    serde_json::json!({
+        "$schema": JSON_SCHEMA_URL,
+        "schema_version": JSON_SCHEMA_VERSION,
+        "tool": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "options": options.settings,
+        "files": files,
+        "containers": containers,
+    })
+}
+
+/// Entry of a file in a versioned JSON document.  Its fields are the
+/// same whichever options were used.
+fn file_json(
+    path: &str,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> serde_json::Value {
+    let functions: Vec<_> = match features {
+        Features::Total { .. } => Vec::new(),
+        Features::BySymbol { symbol_totals, .. } => symbol_totals
+            .iter()
+            .map(|(symbol, usage)| {
+                serde_json::json!({
+                    "symbol": symbol,
+                    "instructions": usage.count,
+                    "bytes": usage.bytes,
+                })
+            })
+            .collect(),
+    };
+    let mut totals = features.totals().to_json();
+
+    totals["bytes"] = serde_json::json!(features.size());
+
+    serde_json::json!({
+        "path": path,
+        "sha256": metadata.sha256,
+        "format": metadata.format,
+        "bitness": metadata.bitness,
+        "decoder_options": metadata.decoder_options,
+        "regions": metadata.regions,
+        "records": features.records(options),
+        "functions": functions,
+        "totals": totals,
+    })
+}
+
+pub fn print_vector_summary_json(
+    path: &str,
+    summary: &VectorSummary,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let data = match summary {
+        VectorSummary::Total { data } => serde_json::to_value(data)?,
+        VectorSummary::BySymbol { data } => serde_json::to_value(data)?,
+    };
+
+    print_analysis_json(
+        path,
+        "vector_usage",
+        data,
+        features,
+        metadata,
+        options,
+    )
+}
+
+pub fn print_unguarded_simd_json(
+    path: &str,
+    data: &BTreeMap<Symbol, usize>,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    print_analysis_json(
+        path,
+        "unguarded_simd",
+        serde_json::to_value(data)?,
+        features,
+        metadata,
+        options,
+    )
+}
+
+pub fn print_sample_shares_json(
+    path: &str,
+    shares: &SampleShares,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let share_values: BTreeMap<_, _> = shares
+        .data
+        .iter()
+        .map(|(name, &count)| (name, shares.share(count)))
+        .collect();
+    let mut data = serde_json::json!({
+        "shares": share_values,
+        "samples_in_binary": shares.in_binary,
+        "total_samples": shares.total,
+    });
+
+    // In versioned output, the samples are the counts of the records.
+    //
+    if options.legacy_json {
+        data["samples"] = features.to_json(&PrintOptions::default());
+    }
+
+    print_analysis_json(
+        path, "profile", data, features, metadata, options,
+    )
+}
+
+/// Prints `data`, the result of an analysis besides counting
+/// instructions, as the field `field` of the file's entry in a
+/// versioned document, or on its own in the legacy shape.
+fn print_analysis_json(
+    path: &str,
+    field: &str,
+    data: serde_json::Value,
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    if options.legacy_json {
+        return print_json_value(data, metadata);
+    }
+
+    let mut file = file_json(path, features, metadata, options);
+
+    file[field] = data;
+
+    println!(
+        "{}",
+        serde_json::to_string(&json_document(
+            vec![file],
+            Vec::new(),
+            options
+        ))?
+    );
+
+    Ok(())
+}
+
+fn print_json_value(
+    data: serde_json::Value,
+    metadata: &Metadata,
+) -> anyhow::Result<()> {
+    let output = serde_json::to_string(&json_value(
+        data,
+        metadata,
+        serde_json::Map::new(),
+    )?)?;
+
+    println!("{output}");
+
+    Ok(())
+}
+
+/// Features of a binary, along with code sizes, totals, and the
+/// vectorization summary if requested.
+fn features_json(
+    features: &Features,
+    metadata: &Metadata,
+    options: &PrintOptions,
+) -> anyhow::Result<serde_json::Value> {
+    let mut extra = serde_json::Map::new();
+
+    if options.sizes {
+        extra.insert("sizes".to_string(), features.sizes_json());
+    }
+
+    if options.summary {
+        extra.insert("totals".to_string(), features.totals_json());
+        extra
+            .insert("summary".to_string(), features.totals().to_json());
+    }
+
+    json_value(features.to_json(options), metadata, extra)
+}
+
+/// Wraps `data` in an object with the metadata and `extra` fields if
+/// there are any.
+fn json_value(
+    data: serde_json::Value,
+    metadata: &Metadata,
+    extra: serde_json::Map<String, serde_json::Value>,
+) -> anyhow::Result<serde_json::Value> {
+    // Keep the output unchanged unless the binary was analyzed with
+    // non-default settings or more than the features were requested,
+    // in which case they are recorded next to the data.
+    //
+    if metadata.is_empty() && extra.is_empty() {
+        return Ok(data);
+    }
+
+    let mut value = serde_json::to_value(metadata)?;
+    let object = value.as_object_mut().expect("should be an object");
+
+    object.insert("features".to_string(), data);
+    object.extend(extra);
+
+    Ok(value)
+}
+
+pub fn print_report_list(
+    report: &Report,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let mut summary: Vec<_> = report.summary().into_iter().collect();
+
+    options.sort(&mut summary, |(extension, paths)| SortKey {
+        extension,
+        usage: paths.iter().map(|&(_, usage)| usage).sum(),
+    });
+
+    for (extension, mut paths) in summary {
+        println!("Files that use {extension}:");
+
+        options.sort(&mut paths, |&(_, usage)| SortKey {
+            extension: "",
+            usage,
+        });
+
+        for (path, usage) in paths.iter() {
+            if options.sizes {
+                println!("- {path} ({} bytes)", usage.bytes);
+            } else {
+                println!("- {path}");
+            }
+        }
+
+        println!();
+    }
+
+    for kind in [ContainerKind::Package, ContainerKind::Layer] {
+        for (container, counts) in report.container_summary(kind) {
+            println!("Extension sets used in {container}:");
+
+            for extension in counts.keys() {
+                println!("- {extension}");
+            }
+
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_report_delimited(
+    report: &Report,
+    separator: char,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let by_encoding = report
+        .files
+        .iter()
+        .any(|(_, features, _)| features.by_encoding());
+
+    println!("{}", delimited_header(by_encoding, separator, options));
+
+    for (path, features, _) in report.files.iter() {
+        for line in delimited_rows(
+            path,
+            features,
+            by_encoding,
+            separator,
+            options,
+        ) {
+            println!("{line}");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_report_matrix(
+    report: &Report,
+    format: MatrixFormat,
+    checkmarks: bool,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let mut rows = Vec::new();
+
+    for (path, features, _) in report.files.iter() {
+        for (symbol, usage) in matrix_rows(features) {
+            rows.push((vec![path.clone(), symbol.to_string()], usage));
+        }
+    }
+
+    print_pivot(
+        &["File", "Function"],
+        rows,
+        format,
+        checkmarks,
+        options,
+    );
+
+    Ok(())
+}
+
+pub fn print_report_table(
+    report: &Report,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    let by_encoding = report
+        .files
+        .iter()
+        .any(|(_, features, _)| features.by_encoding());
+    let mut headers = vec!["File"];
+    let mut rows = Vec::new();
+
+    for (path, features, _) in report.files.iter() {
+        let (file_headers, file_rows) =
+            table_rows(features, by_encoding, options);
+
+        if headers.len() == 1 {
+            headers.extend(file_headers);
+        }
+
+        for row in file_rows {
+            rows.push(
+                [Cell::Text(path.clone())]
+                    .into_iter()
+                    .chain(row)
+                    .collect(),
+            );
+        }
+    }
+
+    print_rows(&headers, &rows);
+    println!();
+
+    if options.summary {
+        let total_rows: Vec<_> = report
+            .files
+            .iter()
+            .map(|(path, features, _)| {
+                let totals = features.totals();
+
+                vec![
+                    Cell::Text(path.clone()),
+                    Cell::from(totals.usage.count),
+                    Cell::Number(percentage(
+                        totals.simd,
+                        totals.usage.count,
+                    )),
+                    Cell::Text(totals.widest().unwrap_or_default()),
+                ]
+            })
+            .collect();
+
+        print_rows(
+            &["File", "Instructions", "SIMD", "Widest SIMD"],
+            &total_rows,
+        );
+        println!();
+    }
+
+    let mut summary: Vec<_> = report.summary().into_iter().collect();
+
+    options.sort(&mut summary, |(extension, paths)| SortKey {
+        extension,
+        usage: paths.iter().map(|&(_, usage)| usage).sum(),
+    });
+
+    let summary_rows: Vec<_> = summary
+        .into_iter()
+        .map(|(extension, paths)| {
+            let mut row =
+                vec![Cell::Text(extension), Cell::from(paths.len())];
+
+            if options.sizes {
+                row.push(Cell::from(
+                    paths
+                        .iter()
+                        .map(|&(_, usage)| usage.bytes)
+                        .sum::<usize>(),
+                ));
+            }
+
+            row
+        })
+        .collect();
+    let summary_headers: &[&str] = if options.sizes {
+        &["Extension", "Files", "Bytes"]
+    } else {
+        &["Extension", "Files"]
+    };
+
+    print_rows(summary_headers, &summary_rows);
+
+    for kind in [ContainerKind::Package, ContainerKind::Layer] {
+        let container_rows: Vec<_> = report
+            .container_summary(kind)
+            .into_iter()
+            .flat_map(|(container, counts)| {
+                counts.into_iter().map(move |(extension, count)| {
+                    vec![
+                        Cell::Text(container.to_string()),
+                        Cell::Text(extension),
+                        Cell::from(count),
+                    ]
+                })
+            })
+            .collect();
+
+        if !container_rows.is_empty() {
+            println!();
+            print_rows(
+                &[kind.title(), "Extension", "Files"],
+                &container_rows,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_report_json(
+    report: &Report,
+    options: &PrintOptions,
+) -> anyhow::Result<()> {
+    if !options.legacy_json {
+        let files = report
+            .files
+            .iter()
+            .map(|(path, features, metadata)| {
+                file_json(path, features, metadata, options)
+            })
+            .collect();
+        let containers = report
+            .containers
+            .iter()
+            .map(|(kind, container, indices)| {
+                serde_json::json!({
+                    "kind": kind.title().to_ascii_lowercase(),
+                    "path": container,
+                    "files": indices
+                        .iter()
+                        .map(|&index| &report.files[index].0)
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        println!(
+            "{}",
+            serde_json::to_string(&json_document(
+                files, containers, options
+            ))?
+        );
+
+        return Ok(());
+    }
+
+    let mut files = serde_json::Map::new();
+
+    for (path, features, metadata) in report.files.iter() {
+        files.insert(
+            path.clone(),
+            features_json(features, metadata, options)?,
+        );
+    }
+
+    let mut output = serde_json::json!({
+        "files": files,
+        "summary": report
+            .summary()
+            .into_iter()
+            .map(|(extension, paths)| {
+                (extension, paths.into_iter().map(|(path, _)| path).collect())
+            })
+            .collect::<BTreeMap<_, Vec<_>>>(),
+    });
+
+    // Packages and layers are listed in the order in which they were
+    // inspected.
+    //
+    for kind in [ContainerKind::Package, ContainerKind::Layer] {
+        let containers = report.container_summary(kind);
+
+        if !containers.is_empty() {
+            output[kind.json_key()] = containers
+                .into_iter()
+                .map(|(container, counts)| {
+                    serde_json::json!({
+                        "path": container,
+                        "summary": counts,
+                    })
+                })
+                .collect();
+        }
+    }
+
+    println!("{}", serde_json::to_string(&output)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Three functions: `a` with one SSE instruction and four others,
+    /// `b` with two AVX instructions and one other, and `c` with one
+    /// other instruction.
+    fn three_functions() -> Binary {
+        #[rustfmt::skip]
+        let code: &[u8] = &[
+            0x0f, 0x28, 0x06,        // movaps xmm0,XMMWORD PTR [rsi]
+            0x90,                    // nop
+            0x90,                    // nop
+            0x90,                    // nop
+            0xc3,                    // ret
+            0xc5, 0xfc, 0x28, 0x06,  // vmovaps ymm0,YMMWORD PTR [rsi]
+            0xc5, 0xfc, 0x28, 0x0e,  // vmovaps ymm1,YMMWORD PTR [rsi]
+            0xc3,                    // ret
+            0xc3,                    // ret
+        ];
+        Binary::from_raw(
+            code,
+            0x400,
+            64,
+            &[
+                (0x400, "a".to_string()),
+                (0x407, "b".to_string()),
+                (0x410, "c".to_string()),
+            ],
+            0,
+            &[],
+        )
+        .unwrap()
+    }
+
+    fn features_of(
+        binary: &Binary,
+        feature_filter: &[&str],
+        show_symbols: ShowSymbols,
+    ) -> Features {
+        let feature_filter: Vec<_> =
+            feature_filter.iter().map(|x| WildMatch::new(x)).collect();
+
+        get_features(
+            binary,
+            &feature_filter,
+            &SymbolFilter {
+                raw: &[],
+                demangled: &[],
+                zmm_only: false,
+                exported_only: false,
+            },
+            show_symbols,
+            false,
+            false,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn sorted_rows<'a>(
+        features: &'a Features,
+        options: &PrintOptions,
+    ) -> Vec<(&'a str, &'a str, usize)> {
+        features
+            .sorted_rows(options)
+            .into_iter()
+            .map(|row| {
+                (row.symbol.unwrap(), row.mnemonic, row.usage.count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort() {
+        let binary = three_functions();
+        let features = features_of(&binary, &[], ShowSymbols::Raw);
+        let options = |sort_by, reverse, top| PrintOptions {
+            sort_by,
+            reverse,
+            top,
+            ..PrintOptions::default()
+        };
+
+        assert_eq!(
+            sorted_rows(&features, &options(SortBy::Name, false, None)),
+            [
+                ("a", "nop", 3),
+                ("a", "movaps", 1),
+                ("a", "ret", 1),
+                ("b", "vmovaps", 2),
+                ("b", "ret", 1),
+                ("c", "ret", 1),
+            ]
+        );
+        // Functions are ranked by their instructions and the top ones
+        // are kept whole.
+        //
+        assert_eq!(
+            sorted_rows(
+                &features,
+                &options(SortBy::Count, false, Some(2))
+            ),
+            [
+                ("a", "nop", 3),
+                ("a", "movaps", 1),
+                ("a", "ret", 1),
+                ("b", "vmovaps", 2),
+                ("b", "ret", 1),
+            ]
+        );
+        assert_eq!(
+            sorted_rows(
+                &features,
+                &options(SortBy::Bytes, true, Some(1))
+            ),
+            [("c", "ret", 1)]
+        );
+
+        // Only the instructions that pass the filter count toward the
+        // ranking.
+        //
+        let features =
+            features_of(&binary, &["sse", "avx"], ShowSymbols::Raw);
+
+        assert_eq!(
+            sorted_rows(
+                &features,
+                &options(SortBy::Count, false, Some(1))
+            ),
+            [("b", "vmovaps", 2)]
+        );
+        assert_eq!(
+            sorted_rows(&features, &options(SortBy::Count, true, None)),
+            [("a", "movaps", 1), ("b", "vmovaps", 2)]
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("main", ','), "main");
+        assert_eq!(quote("f(int, long)", ','), "\"f(int, long)\"");
+        assert_eq!(quote("f(int, long)", '\t'), "f(int, long)");
+        assert_eq!(quote("a\tb", '\t'), "\"a\tb\"");
+        assert_eq!(quote("a\tb", ','), "a\tb");
+        assert_eq!(
+            quote("operator\"\"_x", '\t'),
+            "\"operator\"\"\"\"_x\""
+        );
+        assert_eq!(quote("a\nb", ','), "\"a\nb\"");
+        assert_eq!(quote("a\r\nb", '\t'), "\"a\r\nb\"");
+        assert_eq!(quote("", ','), "");
+    }
+
+    #[test]
+    fn delimited() {
+        let binary = three_functions();
+        let options = PrintOptions {
+            sizes: true,
+            sort_by: SortBy::Count,
+            top: Some(1),
+            ..PrintOptions::default()
+        };
+        let features = features_of(&binary, &["avx"], ShowSymbols::Raw);
+
+        assert_eq!(
+            delimited_header(false, ',', &options),
+            "file,symbol,extension,mnemonic,count,bytes"
+        );
+        assert_eq!(
+            delimited_header(true, '\t', &PrintOptions::default()),
+            "file\tsymbol\textension\tencoding\tmnemonic\tcount"
+        );
+        assert_eq!(
+            delimited_rows("a,b", &features, false, ',', &options),
+            ["\"a,b\",b,avx,vmovaps,2,8"]
+        );
+        assert_eq!(
+            delimited_rows("a,b", &features, true, '\t', &options),
+            ["a,b\tb\tavx\t\tvmovaps\t2\t8"]
+        );
+
+        let features = features_of(&binary, &["sse"], ShowSymbols::No);
+
+        assert_eq!(
+            delimited_rows(
+                "x",
+                &features,
+                false,
+                ',',
+                &PrintOptions::default()
+            ),
+            ["x,,sse,movaps,1"]
+        );
+    }
+
+    fn cells(table: &[Vec<Cell>]) -> Vec<Vec<&str>> {
+        table
+            .iter()
+            .map(|row| row.iter().map(Cell::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn matrix() {
+        let binary = three_functions();
+        let features = features_of(&binary, &[], ShowSymbols::Raw);
+        let rows = || -> Vec<_> {
+            matrix_rows(&features)
+                .into_iter()
+                .map(|(symbol, usage)| {
+                    (vec![symbol.to_string()], usage)
+                })
+                .collect()
+        };
+        let options = PrintOptions::default();
+
+        // Columns are ordered by family rather than by name.
+        //
+        let (headers, table) = pivot(
+            &["Function"],
+            rows(),
+            MatrixFormat::Table,
+            false,
+            &options,
+        );
+
+        assert_eq!(
+            headers,
+            ["Function", "intel8086", "x64", "sse", "avx"]
+        );
+        assert_eq!(
+            cells(&table),
+            [
+                ["a", "3", "1", "1", ""],
+                ["b", "", "1", "", "2"],
+                ["c", "", "1", "", ""],
+            ]
+        );
+
+        // Empty cells are zeros in CSV unless marked by checkmarks.
+        //
+        let (headers, table) = pivot(
+            &["Function"],
+            rows(),
+            MatrixFormat::Csv,
+            false,
+            &options,
+        );
+
+        assert_eq!(
+            csv_lines(&headers, &table),
+            [
+                "Function,intel8086,x64,sse,avx",
+                "a,3,1,1,0",
+                "b,0,1,0,2",
+                "c,0,1,0,0",
+            ]
+        );
+
+        let (headers, table) = pivot(
+            &["Function"],
+            rows(),
+            MatrixFormat::Csv,
+            true,
+            &options,
+        );
+
+        assert_eq!(
+            csv_lines(&headers, &table)[1..],
+            ["a,✓,✓,✓,", "b,,✓,,✓", "c,,✓,,"]
+        );
+
+        // Rows are ranked by their instructions.
+        //
+        let (_, table) = pivot(
+            &["Function"],
+            rows(),
+            MatrixFormat::Table,
+            true,
+            &PrintOptions {
+                sort_by: SortBy::Count,
+                top: Some(2),
+                ..PrintOptions::default()
+            },
+        );
+
+        assert_eq!(
+            cells(&table),
+            [["a", "✓", "✓", "✓", ""], ["b", "", "✓", "", "✓"]]
+        );
+    }
+
+    #[test]
+    fn markdown_matrix() {
+        let rows = vec![(
+            vec!["lib|v.so".to_string(), "operator|".to_string()],
+            BTreeMap::from([(
+                "avx2".to_string(),
+                Usage { count: 2, bytes: 8 },
+            )]),
+        )];
+        let (headers, table) = pivot(
+            &["File", "Function"],
+            rows,
+            MatrixFormat::Markdown,
+            false,
+            &PrintOptions::default(),
+        );
+
+        assert_eq!(
+            markdown_lines(2, &headers, &table),
+            [
+                "| File | Function | avx2 |",
+                "| --- | --- | ---: |",
+                "| lib\\|v.so | operator\\| | 2 |",
+            ]
+        );
+    }
+
+    #[test]
+    fn totals() {
+        let mut totals = Totals::default();
+
+        assert_eq!(totals.summary(), "0.0% of instructions are SIMD");
+        assert_eq!(totals.widest(), None);
+
+        for (features, count) in [
+            (&["intel8086"][..], 10),
+            (&["sse2"], 3),
+            (&["avx2"], 5),
+            (&["avx512f"], 1),
+            (&["avx512f", "avx512vl"], 1),
+            (&["aes"], 2),
+        ] {
+            let features: Vec<_> = features
+                .iter()
+                .map(|x| Feature(x.to_string()))
+                .collect();
+
+            totals.add(
+                &features,
+                Usage {
+                    count,
+                    bytes: 4 * count,
+                },
+            );
+        }
+
+        assert_eq!(
+            totals.usage,
+            Usage {
+                count: 22,
+                bytes: 88
+            }
+        );
+        assert_eq!(totals.simd, 12);
+        assert_eq!(
+            totals.simd_features,
+            BTreeMap::from(
+                [
+                    ("aes", 2),
+                    ("avx2", 5),
+                    ("avx512f", 2),
+                    ("avx512vl", 1),
+                    ("sse2", 3),
+                ]
+                .map(|(name, count)| (name.to_string(), count))
+            )
+        );
+        // The widest registers win over the most instructions.
+        //
+        assert_eq!(totals.widest().as_deref(), Some("AVX-512F"));
+        assert_eq!(
+            totals.summary(),
+            "54.5% of instructions are SIMD; widest: AVX-512F"
+        );
+        assert_eq!(totals.to_json()["simd_share"], 12.0 / 22.0);
+    }
+
+    #[test]
+    fn share_columns() {
+        let binary = three_functions();
+        let features = features_of(&binary, &[], ShowSymbols::Raw);
+        let options = PrintOptions {
+            summary: true,
+            ..PrintOptions::default()
+        };
+        let (headers, rows) = table_rows(&features, false, &options);
+
+        assert_eq!(
+            headers,
+            [
+                "Function",
+                "Extension",
+                "Opcode",
+                "Count",
+                "% of function",
+                "% of total"
+            ]
+        );
+        assert_eq!(
+            cells(&rows)[..2],
+            [
+                ["a", "intel8086", "nop", "3", "60.0%", "33.3%"],
+                ["a", "sse", "movaps", "1", "20.0%", "11.1%"],
+            ]
+        );
+        assert_eq!(
+            cells(&rows)[5],
+            ["c", "x64", "ret", "1", "100.0%", "11.1%"]
+        );
+        assert_eq!(
+            features.totals().summary(),
+            "33.3% of instructions are SIMD; widest: AVX"
+        );
+
+        let features = features_of(&binary, &["avx"], ShowSymbols::No);
+        let (headers, rows) = table_rows(&features, false, &options);
+
+        assert_eq!(
+            headers,
+            ["Extension", "Opcode", "Count", "% of total"]
+        );
+        assert_eq!(cells(&rows), [["avx", "vmovaps", "2", "22.2%"]]);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(percentage(1, 3), "33.3%");
+        assert_eq!(percentage(2, 3), "66.7%");
+        assert_eq!(percentage(1, 1000), "0.1%");
+        assert_eq!(percentage(1, 2001), "0.0%");
+        assert_eq!(percentage(7, 7), "100.0%");
+        assert_eq!(percentage(0, 0), "0.0%");
+        assert_eq!(percentage(5, 0), "0.0%");
+    }
+
+    #[test]
+    fn sizes() {
+        let binary = three_functions();
+        let features = features_of(&binary, &[], ShowSymbols::Raw);
+        let options = PrintOptions {
+            sizes: true,
+            ..PrintOptions::default()
+        };
+
+        // movaps and vmovaps are 3 and 4 bytes long, nop and ret 1.
+        //
+        assert_eq!(features.size(), 17);
+        assert_eq!(
+            cells(&size_rows(
+                features.extension_usage().into_iter().collect(),
+                features.size(),
+                &options
+            )),
+            [
+                ["avx", "8", "47.1%"],
+                ["intel8086", "3", "17.6%"],
+                ["sse", "3", "17.6%"],
+                ["x64", "3", "17.6%"],
+            ]
+        );
+        assert_eq!(
+            cells(&size_rows(
+                features
+                    .symbol_usage()
+                    .into_iter()
+                    .map(|(name, usage)| (name.to_string(), usage))
+                    .collect(),
+                features.size(),
+                &PrintOptions {
+                    sort_by: SortBy::Bytes,
+                    ..PrintOptions::default()
+                }
+            )),
+            [
+                ["b", "9", "52.9%"],
+                ["a", "7", "41.2%"],
+                ["c", "1", "5.9%"]
+            ]
+        );
+
+        let (headers, rows) = table_rows(&features, false, &options);
+
+        assert_eq!(
+            headers,
+            [
+                "Function",
+                "Extension",
+                "Opcode",
+                "Count",
+                "Bytes",
+                "% of code"
+            ]
+        );
+        assert_eq!(
+            cells(&rows)[3],
+            ["b", "avx", "vmovaps", "2", "8", "47.1%"]
+        );
+
+        // Shares are of all code, including the code of extension sets
+        // that are filtered out.
+        //
+        let features = features_of(&binary, &["sse"], ShowSymbols::No);
+
+        assert_eq!(features.size(), 17);
+        assert_eq!(
+            cells(&table_rows(&features, false, &options).1),
+            [["sse", "movaps", "1", "3", "17.6%"]]
+        );
+        assert_eq!(
+            cells(&size_rows(
+                vec![("sse".to_string(), Usage { count: 1, bytes: 3 })],
+                0,
+                &options
+            )),
+            [["sse", "3", "0.0%"]]
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(
+            include_str!("../schema/lsx86features.schema.json"),
+        )
+        .unwrap();
+
+        assert_eq!(schema["$id"], JSON_SCHEMA_URL);
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            JSON_SCHEMA_VERSION
+        );
+
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let binary = three_functions();
+        let metadata = Metadata::new(&binary);
+        let args = crate::cli::Args::parse_from([
+            "lsx86features",
+            "-s",
+            "-F",
+            "sse,avx",
+            "--bitness",
+            ".text16=16",
+            "--sort-by",
+            "count",
+            "--size",
+            "--summary",
+            "demo",
+        ]);
+        let options = PrintOptions {
+            sizes: true,
+            sort_by: SortBy::Count,
+            summary: true,
+            settings: crate::settings(&args),
+            ..PrintOptions::default()
+        };
+        let files = vec![
+            file_json(
+                "total",
+                &features_of(&binary, &[], ShowSymbols::No),
+                &metadata,
+                &options,
+            ),
+            file_json(
+                "by-symbol",
+                &features_of(
+                    &binary,
+                    &["sse", "avx"],
+                    ShowSymbols::Raw,
+                ),
+                &metadata,
+                &options,
+            ),
+        ];
+        let containers = vec![serde_json::json!({
+            "kind": "package",
+            "path": "demo.deb",
+            "files": ["total", "by-symbol"],
+        })];
+        let document = json_document(files, containers, &options);
+
+        assert!(
+            validator.is_valid(&document),
+            "{}",
+            validator
+                .iter_errors(&document)
+                .map(|error| error.to_string())
+                .join("\n")
+        );
+
+        // Every setting is listed in the schema, which rejects unknown
+        // ones.
+        //
+        let mut unknown = document.clone();
+
+        unknown["options"]["renamed_setting"] = true.into();
+
+        assert!(!validator.is_valid(&unknown));
+        assert_eq!(document["options"]["feature_filter"][1], "avx");
+
+        // Functions are ranked by the instructions that pass the
+        // filter.
+        //
+        assert_eq!(document["files"][1]["format"], "raw");
+        assert_eq!(document["files"][1]["records"][0]["symbol"], "b");
+    }
 }
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context};
+use goblin::elf::compression_header::ELFCOMPRESS_ZLIB;
+use std::io::Read;
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+/// Magic numbers of the first block of a bzip2 stream and of the end of
+/// an empty stream, which follow the magic number and the block size.
+const BZIP2_BLOCK_MAGICS: [&[u8]; 2] = [
+    &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59],
+    &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90],
+];
+
+/// Compression type of `SHF_COMPRESSED` sections compressed with zstd,
+/// which `goblin` does not define.
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+/// Whether `data` starts with the magic number of gzip, xz, or zstd.
+pub fn is_compressed(data: &[u8]) -> bool {
+    [GZIP_MAGIC, XZ_MAGIC, ZSTD_MAGIC]
+        .iter()
+        .any(|magic| data.starts_with(magic))
+}
+
+/// Reads `path` and decompresses it if it is compressed with gzip, xz,
+/// or zstd.
+pub fn read_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+
+    Ok(match decompress(&data)? {
+        Some(decompressed) => decompressed,
+        None => data,
+    })
+}
+
+/// Decompresses `data` if it starts with the magic number of gzip, xz,
+/// or zstd.  Returns `None` if `data` is not compressed.
+pub fn decompress(data: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut result = Vec::new();
+
+    if data.starts_with(GZIP_MAGIC) {
+        flate2::read::MultiGzDecoder::new(data)
+            .read_to_end(&mut result)
+            .context("Could not decompress gzip data")?;
+    } else if data.starts_with(XZ_MAGIC) {
+        lzma_rs::xz_decompress(&mut &data[..], &mut result)
+            .context("Could not decompress xz data")?;
+    } else if data.starts_with(ZSTD_MAGIC) {
+        zstd_decompress(data, &mut result)?;
+    } else if is_bzip2(data) {
+        return Err(anyhow!("bzip2 compression is not supported"));
+    } else {
+        return Ok(None);
+    }
+
+    Ok(Some(result))
+}
+
+/// Whether `data` starts with a bzip2 header rather than with text or
+/// code that happens to start with `BZh`.
+fn is_bzip2(data: &[u8]) -> bool {
+    data.strip_prefix(BZIP2_MAGIC)
+        .and_then(|x| x.split_first())
+        .is_some_and(|(level, rest)| {
+            (b'1'..=b'9').contains(level)
+                && BZIP2_BLOCK_MAGICS
+                    .iter()
+                    .any(|x| rest.starts_with(x))
+        })
+}
+
+/// Decompresses the contents of an `SHF_COMPRESSED` section that follow
+/// the compression header.
+pub fn decompress_section(
+    ch_type: u32,
+    data: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = Vec::new();
+
+    match ch_type {
+        ELFCOMPRESS_ZLIB => {
+            flate2::read::ZlibDecoder::new(data)
+                .read_to_end(&mut result)
+                .context("Could not decompress zlib data")?;
+        }
+        ELFCOMPRESS_ZSTD => zstd_decompress(data, &mut result)?,
+        _ => {
+            return Err(anyhow!(
+                "Unknown section compression type: {ch_type}"
+            ))
+        }
+    }
+
+    Ok(result)
+}
+
+/// Decompresses all zstd frames in `data`.
+pub fn zstd_decompress(
+    mut data: &[u8],
+    result: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    while !data.is_empty() {
+        let mut decoder =
+            ruzstd::decoding::StreamingDecoder::new(&mut data)
+                .map_err(|error| anyhow!("{error}"))
+                .context("Could not decompress zstd data")?;
+
+        decoder
+            .read_to_end(result)
+            .context("Could not decompress zstd data")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+
+        encoder.write_all(b"\x7fELF").unwrap();
+
+        let data = encoder.finish().unwrap();
+
+        assert_eq!(
+            decompress(&data).unwrap(),
+            Some(b"\x7fELF".to_vec())
+        );
+        assert_eq!(decompress(b"\x7fELF").unwrap(), None);
+    }
+
+    #[test]
+    fn bzip2() {
+        // `bzip2 < /dev/null`
+        //
+        let empty = b"BZh9\x17\x72\x45\x38\x50\x90\x00\x00\x00\x00";
+
+        assert!(decompress(empty).is_err());
+        assert_eq!(decompress(b"BZh is not compressed").unwrap(), None);
+        assert_eq!(decompress(b"BZh").unwrap(), None);
+    }
+
+    #[test]
+    fn section() {
+        let mut encoder = flate2::write::ZlibEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        );
+
+        encoder.write_all(&[0x90; 64]).unwrap();
+
+        let data = encoder.finish().unwrap();
+
+        assert_eq!(
+            decompress_section(ELFCOMPRESS_ZLIB, &data).unwrap(),
+            vec![0x90; 64]
+        );
+        assert!(decompress_section(3, &data).is_err());
+    }
+}
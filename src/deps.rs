@@ -0,0 +1,312 @@
+use anyhow::Context;
+use goblin::elf::Elf;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::binary;
+
+/// Directories searched after `/etc/ld.so.cache`, see ld.so(8).
+const DEFAULT_DIRS_32: &[&str] = &["/lib", "/usr/lib"];
+const DEFAULT_DIRS_64: &[&str] =
+    &["/lib64", "/usr/lib64", "/lib", "/usr/lib"];
+
+/// Magic string of the format of `/etc/ld.so.cache` used by glibc 2.32
+/// and later.  Older caches contain the same format after a header in
+/// the legacy `ld.so-1.7.0` format.
+const CACHE_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+const CACHE_HEADER_SIZE: usize = 48;
+const CACHE_ENTRY_SIZE: usize = 24;
+
+/// Returns the shared libraries that `path` depends on, directly or
+/// transitively, in the order in which they are first needed.
+///
+/// Libraries are resolved the same way as by ld.so(8): using
+/// `DT_RPATH` (if there is no `DT_RUNPATH`), `LD_LIBRARY_PATH`,
+/// `DT_RUNPATH`, `/etc/ld.so.cache`, and finally the default library
+/// directories.  If `sysroot` is given, then absolute paths are looked
+/// up relative to it, including `DT_NEEDED` entries that are paths.
+///
+/// Libraries that cannot be found are skipped with a warning, like
+/// ldd(1) reports them as "not found" and lists the others.
+pub fn dependencies(
+    path: &Path,
+    sysroot: Option<&Path>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let cache = read_cache(&with_sysroot(
+        Path::new("/etc/ld.so.cache"),
+        sysroot,
+    ));
+    let ld_library_path = std::env::var("LD_LIBRARY_PATH")
+        .map(|x| split_paths(&x))
+        .unwrap_or_default();
+
+    let mut result = Vec::new();
+    let mut seen_paths = HashSet::from([path.to_path_buf()]);
+    let mut queue = VecDeque::from([path.to_path_buf()]);
+
+    while let Some(object_path) = queue.pop_front() {
+        let data = std::fs::read(&object_path).with_context(|| {
+            format!("Could not read '{}'", object_path.display())
+        })?;
+        let elf = Elf::parse(&data).with_context(|| {
+            format!("Could not parse '{}'", object_path.display())
+        })?;
+        let origin = origin_dir(&object_path);
+        let expand = |paths: &[&str]| -> Vec<PathBuf> {
+            paths
+                .iter()
+                .flat_map(|x| split_paths(x))
+                .map(|dir| expand_origin(&dir, &origin, sysroot))
+                .collect()
+        };
+        let rpaths = if elf.runpaths.is_empty() {
+            expand(&elf.rpaths)
+        } else {
+            Vec::new()
+        };
+        let runpaths = expand(&elf.runpaths);
+        let default_dirs = if elf.is_64 {
+            DEFAULT_DIRS_64
+        } else {
+            DEFAULT_DIRS_32
+        };
+
+        // Libraries are deduplicated by path rather than by name,
+        // since a name that one object cannot resolve may be found
+        // through the search paths of another.
+        //
+        for &name in elf.libraries.iter() {
+            let candidates: Vec<PathBuf> = if name.contains('/') {
+                vec![with_sysroot(Path::new(name), sysroot)]
+            } else {
+                rpaths
+                    .iter()
+                    .cloned()
+                    .chain(ld_library_path.iter().map(|dir| {
+                        with_sysroot(Path::new(dir), sysroot)
+                    }))
+                    .chain(runpaths.iter().cloned())
+                    .map(|dir| dir.join(name))
+                    .chain(cache.iter().filter(|(x, _)| x == name).map(
+                        |(_, x)| with_sysroot(Path::new(x), sysroot),
+                    ))
+                    .chain(default_dirs.iter().map(|dir| {
+                        with_sysroot(Path::new(dir), sysroot).join(name)
+                    }))
+                    .collect()
+            };
+
+            let Some(library_path) = candidates
+                .into_iter()
+                .find(|x| is_compatible(x, elf.header.e_machine))
+            else {
+                eprintln!(
+                    "Skipping '{name}': shared library needed by '{}' \
+                     not found",
+                    object_path.display()
+                );
+
+                continue;
+            };
+
+            if seen_paths.insert(library_path.clone()) {
+                result.push(library_path.clone());
+                queue.push_back(library_path);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn split_paths(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .filter(|x| !x.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn with_sysroot(path: &Path, sysroot: Option<&Path>) -> PathBuf {
+    match (sysroot, path.strip_prefix("/")) {
+        (Some(sysroot), Ok(relative)) => sysroot.join(relative),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Returns the directory that `$ORIGIN` refers to in the search paths
+/// of `path`, also if `path` is a bare relative path such as `app`.
+fn origin_dir(path: &Path) -> PathBuf {
+    let path =
+        path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Expands `$ORIGIN` to the directory of the object that contains the
+/// search path.  Since `origin` is already inside `sysroot`, the
+/// sysroot is only prepended to search paths without `$ORIGIN`.
+fn expand_origin(
+    dir: &str,
+    origin: &Path,
+    sysroot: Option<&Path>,
+) -> PathBuf {
+    let origin_str = origin.to_string_lossy();
+
+    if dir.contains("$ORIGIN") || dir.contains("${ORIGIN}") {
+        PathBuf::from(
+            dir.replace("${ORIGIN}", &origin_str)
+                .replace("$ORIGIN", &origin_str),
+        )
+    } else {
+        with_sysroot(Path::new(dir), sysroot)
+    }
+}
+
+/// Whether `path` is an ELF file for the architecture `e_machine`.
+fn is_compatible(path: &Path, e_machine: u16) -> bool {
+    binary::read_header(path)
+        .and_then(|header| binary::elf_machine(&header))
+        == Some(e_machine)
+}
+
+/// Reads the library names and paths listed in `/etc/ld.so.cache`.  A
+/// missing or malformed cache is treated as empty.
+fn read_cache(path: &Path) -> Vec<(String, String)> {
+    std::fs::read(path)
+        .map(|data| parse_cache(&data))
+        .unwrap_or_default()
+}
+
+fn parse_cache(data: &[u8]) -> Vec<(String, String)> {
+    let Some(begin) = data
+        .windows(CACHE_MAGIC.len())
+        .position(|x| x == CACHE_MAGIC)
+    else {
+        return Vec::new();
+    };
+    let cache = &data[begin..];
+    let read_u32 = |offset: usize| -> Option<usize> {
+        let bytes = cache.get(offset..offset + 4)?;
+
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?) as usize)
+    };
+    // String offsets are relative to the beginning of the cache.
+    let read_str = |offset: usize| -> Option<String> {
+        let bytes = cache.get(offset..)?;
+        let end = bytes.iter().position(|&x| x == 0)?;
+
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    };
+
+    let Some(count) = read_u32(CACHE_MAGIC.len()) else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+
+    for index in 0..count {
+        let entry = CACHE_HEADER_SIZE + index * CACHE_ENTRY_SIZE;
+        let (Some(key), Some(value)) =
+            (read_u32(entry + 4), read_u32(entry + 8))
+        else {
+            break;
+        };
+
+        if let (Some(name), Some(path)) =
+            (read_str(key), read_str(value))
+        {
+            result.push((name, path));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache() {
+        let mut data = b"ld.so-1.7.0\0\0\0\0\0".to_vec();
+        let begin = data.len();
+        let strings = CACHE_HEADER_SIZE + 2 * CACHE_ENTRY_SIZE;
+
+        data.extend_from_slice(CACHE_MAGIC);
+        data.extend_from_slice(&2u32.to_ne_bytes()); // nlibs
+        data.resize(begin + CACHE_HEADER_SIZE, 0);
+
+        for (key, value) in [(0, 10), (25, 35)] {
+            data.extend_from_slice(&0x0303i32.to_ne_bytes()); // flags
+            data.extend_from_slice(
+                &((strings + key) as u32).to_ne_bytes(),
+            );
+            data.extend_from_slice(
+                &((strings + value) as u32).to_ne_bytes(),
+            );
+            data.extend_from_slice(&[0; 12]); // osversion, hwcap
+        }
+
+        data.extend_from_slice(b"libm.so.6\0/lib/libm.so.6\0");
+        data.extend_from_slice(b"libc.so.6\0/lib/libc.so.6\0");
+
+        assert_eq!(
+            parse_cache(&data),
+            vec![
+                ("libm.so.6".to_string(), "/lib/libm.so.6".to_string()),
+                ("libc.so.6".to_string(), "/lib/libc.so.6".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_libraries() {
+        let path = std::env::current_exe().unwrap();
+        let sysroot = Path::new("/nonexistent-sysroot");
+
+        assert!(!dependencies(&path, None).unwrap().is_empty());
+        assert!(dependencies(&path, Some(sysroot)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn origin() {
+        let cwd =
+            std::env::current_dir().unwrap().canonicalize().unwrap();
+
+        assert_eq!(origin_dir(Path::new("Cargo.toml")), cwd);
+        assert_eq!(
+            origin_dir(Path::new("missing")),
+            PathBuf::from(".")
+        );
+        assert_eq!(
+            origin_dir(Path::new("/missing/app")),
+            PathBuf::from("/missing")
+        );
+    }
+
+    #[test]
+    fn origin_and_sysroot() {
+        let sysroot = Some(Path::new("/sysroot"));
+        let origin = Path::new("/sysroot/opt/app/bin");
+
+        assert_eq!(
+            expand_origin("$ORIGIN/../lib", origin, sysroot),
+            PathBuf::from("/sysroot/opt/app/bin/../lib")
+        );
+        assert_eq!(
+            expand_origin("${ORIGIN}", origin, sysroot),
+            PathBuf::from("/sysroot/opt/app/bin")
+        );
+        assert_eq!(
+            expand_origin("/opt/app/lib", origin, sysroot),
+            PathBuf::from("/sysroot/opt/app/lib")
+        );
+        assert_eq!(
+            expand_origin("/opt/app/lib", origin, None),
+            PathBuf::from("/opt/app/lib")
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::BTreeSet;
+
+/// A group of related extensions, named in lowercase like the
+/// extensions themselves.
+pub struct Family {
+    pub name: &'static str,
+    /// Family that this one is a subfamily of.
+    pub parent: Option<&'static str>,
+    /// Lowercase names of the `iced_x86::CpuidFeature` values in the
+    /// family.
+    pub features: &'static [&'static str],
+    pub simd: Simd,
+}
+
+/// Whether the instructions of a family are SIMD instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Simd {
+    No,
+    /// SIMD instructions on vector registers of this many bits.
+    Width(u32),
+    /// Some of them are, namely those of the extensions that imply a
+    /// SIMD extension of another family, e.g., AES implies SSE2.
+    Mixed,
+}
+
+/// Family of the extensions that are not in any other family.
+pub const OTHER: &str = "other";
+
+pub const FAMILIES: &[Family] = &[
+    Family {
+        name: "base",
+        parent: None,
+        features: &[
+            "intel8086",
+            "intel8086_only",
+            "intel186",
+            "intel286",
+            "intel286_only",
+            "intel386",
+            "intel386_only",
+            "intel386_a0_only",
+            "intel486",
+            "intel486_a_only",
+            "x64",
+            "cmov",
+            "cx8",
+            "cmpxchg16b",
+            "cpuid",
+            "multibytenop",
+            "pause",
+            "movbe",
+            "syscall",
+            "sep",
+            "tsc",
+            "rdtscp",
+            "rdpid",
+            "rdpmc",
+            "msr",
+            "msrlist",
+            "wrmsrns",
+            "fsgsbase",
+            "serialize",
+            "monitor",
+            "monitorx",
+            "waitpkg",
+            "cmpccxadd",
+            "rao_int",
+            "rdpru",
+        ],
+        simd: Simd::No,
+    },
+    Family {
+        name: "x87",
+        parent: None,
+        features: &[
+            "fpu",
+            "fpu287",
+            "fpu287xl_only",
+            "fpu387",
+            "fpu387sl_only",
+            "cyrix_fpu",
+        ],
+        simd: Simd::No,
+    },
+    Family {
+        name: "mmx",
+        parent: None,
+        features: &["mmx", "cyrix_emmi"],
+        simd: Simd::Width(64),
+    },
+    Family {
+        name: "3dnow",
+        parent: None,
+        features: &["d3now", "d3nowext", "cyrix_d3now"],
+        simd: Simd::Width(64),
+    },
+    Family {
+        name: "sse",
+        parent: None,
+        features: &[
+            "sse", "sse2", "sse3", "ssse3", "sse4_1", "sse4_2", "sse4a",
+        ],
+        simd: Simd::Width(128),
+    },
+    Family {
+        name: "avx",
+        parent: None,
+        features: &[
+            "avx",
+            "avx2",
+            "fma",
+            "f16c",
+            "avx_vnni",
+            "avx_vnni_int8",
+            "avx_vnni_int16",
+            "avx_ifma",
+            "avx_ne_convert",
+        ],
+        simd: Simd::Width(256),
+    },
+    Family {
+        name: "xop",
+        parent: None,
+        features: &["xop", "fma4"],
+        simd: Simd::Width(256),
+    },
+    Family {
+        name: "avx512",
+        parent: None,
+        features: &[],
+        simd: Simd::Width(512),
+    },
+    Family {
+        name: "avx512-core",
+        parent: Some("avx512"),
+        features: &[
+            "avx512f", "avx512cd", "avx512vl", "avx512bw", "avx512dq",
+        ],
+        simd: Simd::Width(512),
+    },
+    Family {
+        name: "avx512-integer",
+        parent: Some("avx512"),
+        features: &[
+            "avx512_ifma",
+            "avx512_vbmi",
+            "avx512_vbmi2",
+            "avx512_bitalg",
+            "avx512_vpopcntdq",
+        ],
+        simd: Simd::Width(512),
+    },
+    Family {
+        name: "avx512-ai",
+        parent: Some("avx512"),
+        features: &[
+            "avx512_vnni",
+            "avx512_bf16",
+            "avx512_fp16",
+            "avx512_vp2intersect",
+        ],
+        simd: Simd::Width(512),
+    },
+    Family {
+        name: "avx512-xeon-phi",
+        parent: Some("avx512"),
+        features: &[
+            "avx512er",
+            "avx512pf",
+            "avx512_4fmaps",
+            "avx512_4vnniw",
+        ],
+        simd: Simd::Width(512),
+    },
+    Family {
+        name: "amx",
+        parent: None,
+        features: &[
+            "amx_tile",
+            "amx_int8",
+            "amx_bf16",
+            "amx_fp16",
+            "amx_complex",
+        ],
+        simd: Simd::No,
+    },
+    Family {
+        name: "crypto",
+        parent: None,
+        features: &[
+            "aes",
+            "vaes",
+            "pclmulqdq",
+            "vpclmulqdq",
+            "gfni",
+            "sha",
+            "sha512",
+            "sm3",
+            "sm4",
+            "kl",
+            "aeskle",
+            "wide_kl",
+            "rdrand",
+            "rdseed",
+            "padlock_ace",
+            "padlock_phe",
+            "padlock_pmm",
+            "padlock_rng",
+            "padlock_gmi",
+        ],
+        simd: Simd::Mixed,
+    },
+    Family {
+        name: "bmi",
+        parent: None,
+        features: &["bmi1", "bmi2", "lzcnt", "popcnt", "tbm", "adx"],
+        simd: Simd::No,
+    },
+    Family {
+        name: "tsx",
+        parent: None,
+        features: &["rtm", "hle", "hle_or_rtm", "tsxldtrk"],
+        simd: Simd::No,
+    },
+    Family {
+        name: "cache",
+        parent: None,
+        features: &[
+            "clfsh",
+            "clflushopt",
+            "clwb",
+            "cldemote",
+            "clzero",
+            "prefetchw",
+            "prefetchwt1",
+            "prefetchiti",
+            "pcommit",
+            "mcommit",
+            "wbnoinvd",
+            "invpcid",
+            "invlpgb",
+            "movdiri",
+            "movdir64b",
+        ],
+        simd: Simd::No,
+    },
+    Family {
+        name: "xsave",
+        parent: None,
+        features: &["fxsr", "xsave", "xsavec", "xsaveopt", "xsaves"],
+        simd: Simd::No,
+    },
+    Family {
+        name: "security",
+        parent: None,
+        features: &[
+            "cet_ibt", "cet_ss", "sgx1", "smap", "pku", "mpx", "smx",
+            "pconfig", "tse",
+        ],
+        simd: Simd::No,
+    },
+    Family {
+        name: "virtualization",
+        parent: None,
+        features: &[
+            "vmx",
+            "svm",
+            "skinit",
+            "skinit_or_svm",
+            "invept",
+            "invvpid",
+            "sev_es",
+            "sev_snp",
+            "tdx",
+            "rmpquery",
+        ],
+        simd: Simd::No,
+    },
+];
+
+/// Extensions that CPUs with a given extension also support, e.g.,
+/// AVX2 implies AVX.  Implications are transitive and only list the
+/// closest extensions.
+const IMPLICATIONS: &[(&str, &[&str])] = &[
+    ("x64", &["sse2", "cmov", "cx8", "fxsr"]),
+    ("sse2", &["sse"]),
+    ("sse3", &["sse2"]),
+    ("ssse3", &["sse3"]),
+    ("sse4_1", &["ssse3"]),
+    ("sse4_2", &["sse4_1"]),
+    ("sse4a", &["sse3"]),
+    ("avx", &["sse4_2", "xsave"]),
+    ("avx2", &["avx"]),
+    ("fma", &["avx"]),
+    ("f16c", &["avx"]),
+    ("xop", &["avx"]),
+    ("fma4", &["avx"]),
+    ("avx_vnni", &["avx2"]),
+    ("avx_vnni_int8", &["avx2"]),
+    ("avx_vnni_int16", &["avx2"]),
+    ("avx_ifma", &["avx2"]),
+    ("avx_ne_convert", &["avx2"]),
+    ("avx512f", &["avx2", "fma", "f16c"]),
+    ("avx512cd", &["avx512f"]),
+    ("avx512vl", &["avx512f"]),
+    ("avx512bw", &["avx512f"]),
+    ("avx512dq", &["avx512f"]),
+    ("avx512er", &["avx512f"]),
+    ("avx512pf", &["avx512f"]),
+    ("avx512_4fmaps", &["avx512f"]),
+    ("avx512_4vnniw", &["avx512f"]),
+    ("avx512_ifma", &["avx512f"]),
+    ("avx512_vbmi", &["avx512bw"]),
+    ("avx512_vbmi2", &["avx512bw"]),
+    ("avx512_bitalg", &["avx512bw"]),
+    ("avx512_vpopcntdq", &["avx512f"]),
+    ("avx512_vnni", &["avx512f"]),
+    ("avx512_bf16", &["avx512bw"]),
+    ("avx512_fp16", &["avx512bw"]),
+    ("avx512_vp2intersect", &["avx512f"]),
+    ("amx_int8", &["amx_tile"]),
+    ("amx_bf16", &["amx_tile"]),
+    ("amx_fp16", &["amx_tile"]),
+    ("amx_complex", &["amx_tile"]),
+    ("aes", &["sse2"]),
+    ("pclmulqdq", &["sse2"]),
+    ("sha", &["sse2"]),
+    ("gfni", &["sse2"]),
+    ("vaes", &["avx", "aes"]),
+    ("vpclmulqdq", &["avx", "pclmulqdq"]),
+    ("sha512", &["avx2"]),
+    ("sm3", &["avx"]),
+    ("sm4", &["avx2"]),
+    ("kl", &["sse2"]),
+    ("aeskle", &["kl"]),
+    ("wide_kl", &["aeskle"]),
+    ("d3nowext", &["d3now"]),
+    ("d3now", &["mmx"]),
+];
+
+/// The most specific family of an extension, or `OTHER`.
+pub fn family(feature: &str) -> &'static str {
+    FAMILIES
+        .iter()
+        .find(|family| family.features.contains(&feature))
+        .map_or(OTHER, |family| family.name)
+}
+
+/// Width of the vector registers of a SIMD extension, or `None` if
+/// `feature` is not one.
+pub fn simd_width(feature: &str) -> Option<u32> {
+    let family = FAMILIES
+        .iter()
+        .find(|family| family.features.contains(&feature))?;
+
+    match family.simd {
+        Simd::No => None,
+        Simd::Width(width) => Some(width),
+        Simd::Mixed => implied(&BTreeSet::from([feature.to_string()]))
+            .into_iter()
+            .filter_map(simd_width)
+            .max(),
+    }
+}
+
+/// Position of a family, or of the family of an extension, in
+/// `FAMILIES`.  Extensions in no family come last.
+pub fn rank(name: &str) -> usize {
+    let name = if FAMILIES.iter().any(|family| family.name == name) {
+        name
+    } else {
+        family(name)
+    };
+
+    FAMILIES
+        .iter()
+        .position(|family| family.name == name)
+        .unwrap_or(FAMILIES.len())
+}
+
+/// The family of an extension and the families that contain it, from
+/// the most to the least specific.
+pub fn families(feature: &str) -> Vec<&'static str> {
+    let mut result = vec![family(feature)];
+
+    while let Some(parent) = FAMILIES
+        .iter()
+        .find(|family| Some(&family.name) == result.last())
+        .and_then(|family| family.parent)
+    {
+        result.push(parent);
+    }
+
+    result
+}
+
+/// Extensions implied by `features`, directly or transitively, that
+/// are not in `features` themselves.
+pub fn implied(features: &BTreeSet<String>) -> BTreeSet<&'static str> {
+    let mut result = BTreeSet::new();
+    let mut pending: Vec<&str> =
+        features.iter().map(String::as_str).collect();
+
+    while let Some(feature) = pending.pop() {
+        let Some((_, implied)) =
+            IMPLICATIONS.iter().find(|&&(name, _)| name == feature)
+        else {
+            continue;
+        };
+
+        for &name in implied.iter() {
+            if !features.contains(name) && result.insert(name) {
+                pending.push(name);
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn feature_families() {
+        assert_eq!(family("ssse3"), "sse");
+        assert_eq!(family("sse4_1"), "sse");
+        assert_eq!(family("avx2"), "avx");
+        assert_eq!(family("umov"), OTHER);
+        assert_eq!(families("avx512bw"), vec!["avx512-core", "avx512"]);
+        assert_eq!(families("bmi2"), vec!["bmi"]);
+        assert!(rank("sse4_2") < rank("avx2"));
+        assert!(rank("avx2") < rank("avx512-core"));
+        assert_eq!(rank("umov"), FAMILIES.len());
+    }
+
+    #[test]
+    fn feature_names() {
+        let names: BTreeSet<String> = iced_x86::CpuidFeature::values()
+            .map(crate::command::lowercase)
+            .collect();
+        let listed =
+            FAMILIES.iter().flat_map(|family| family.features).chain(
+                IMPLICATIONS.iter().flat_map(|(feature, implied)| {
+                    std::iter::once(feature).chain(implied.iter())
+                }),
+            );
+
+        for name in listed {
+            assert!(names.contains(*name), "{name}");
+        }
+
+        assert_eq!(family("monitor"), "base");
+        assert_eq!(family("cmpccxadd"), "base");
+        assert_eq!(family("invpcid"), "cache");
+    }
+
+    #[test]
+    fn simd_widths() {
+        assert_eq!(simd_width("mmx"), Some(64));
+        assert_eq!(simd_width("ssse3"), Some(128));
+        assert_eq!(simd_width("fma"), Some(256));
+        assert_eq!(simd_width("avx512_vbmi2"), Some(512));
+        assert_eq!(simd_width("aes"), Some(128));
+        assert_eq!(simd_width("vaes"), Some(256));
+        assert_eq!(simd_width("rdrand"), None);
+        assert_eq!(simd_width("x64"), None);
+        assert_eq!(simd_width("amx_tile"), None);
+        assert_eq!(simd_width("umov"), None);
+    }
+
+    #[test]
+    fn implications() {
+        let features =
+            BTreeSet::from(["avx2".to_string(), "sse2".to_string()]);
+
+        assert_eq!(
+            implied(&features),
+            BTreeSet::from([
+                "avx", "sse", "sse3", "sse4_1", "sse4_2", "ssse3",
+                "xsave",
+            ])
+        );
+        assert_eq!(
+            implied(&BTreeSet::from(["x64".to_string()])).len(),
+            5
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use anyhow::{anyhow, Context};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::binary;
+use crate::compression;
+use crate::package::{self, Member};
+
+/// Prefix of the files that delete a file or directory of a lower
+/// layer.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Name of the file that hides the contents of its directory in lower
+/// layers.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// Architectures of images in an OCI image index that can contain x86
+/// code.
+const ARCHITECTURES: &[&str] = &["amd64", "386"];
+
+/// A layer of a container image along with the x86 binaries that it
+/// contributes to the final file system of the image.
+pub struct Layer {
+    /// Name of the image, e.g., `ubuntu:24.04` or, for an image in an
+    /// OCI image index, `ubuntu:24.04 (amd64)`.
+    pub image: String,
+    /// Shortened digest of the layer, e.g., `sha256:0123456789ab`.
+    pub name: String,
+    pub members: Vec<Member>,
+}
+
+/// An image listed in `manifest.json` or `index.json`: its name and the
+/// paths of its layers.
+type Image = (String, Vec<String>);
+
+/// Reads the layers of the images saved by `docker save` or stored in
+/// the OCI image layout.  Returns `None` if `files` contains neither a
+/// `manifest.json` nor an `index.json`.
+///
+/// The layers of each image are unpacked in order, and files that are
+/// deleted or replaced by a later layer of the same image are not
+/// included.  Layers shared by several images are listed once per
+/// image.
+pub fn read_layers(
+    files: &[Member],
+) -> anyhow::Result<Option<Vec<Layer>>> {
+    let files: HashMap<&str, &[u8]> = files
+        .iter()
+        .map(|x| (x.path.trim_start_matches('/'), x.data.as_slice()))
+        .collect();
+
+    let images = if let Some(data) = files.get("manifest.json") {
+        docker_images(data)?
+    } else if let Some(data) = files.get("index.json") {
+        oci_images(&files, data)?
+    } else {
+        return Ok(None);
+    };
+    let mut result = Vec::new();
+
+    for (image, layer_paths) in images {
+        result.extend(image_layers(&files, &image, &layer_paths)?);
+    }
+
+    Ok(Some(result))
+}
+
+/// Unpacks the layers of a single image.
+fn image_layers(
+    files: &HashMap<&str, &[u8]>,
+    image: &str,
+    layer_paths: &[String],
+) -> anyhow::Result<Vec<Layer>> {
+    // Path of each binary in the final file system, mapped to the
+    // index of the layer that added it and its contents.
+    //
+    let mut binaries: BTreeMap<String, (usize, Vec<u8>)> =
+        BTreeMap::new();
+    let mut names = Vec::new();
+
+    for (index, layer_path) in layer_paths.iter().enumerate() {
+        let data = files.get(layer_path.as_str()).ok_or_else(|| {
+            anyhow!("Image does not contain layer '{layer_path}'")
+        })?;
+        let tar = compression::decompress(data)?;
+        let members = package::read_tar(tar.as_deref().unwrap_or(data))
+            .with_context(|| {
+                format!("Could not read layer '{layer_path}'")
+            })?;
+
+        // Whiteouts only apply to lower layers, so they are processed
+        // before any file of this layer is added.
+        //
+        for member in members.iter() {
+            let (dir, name) = member
+                .path
+                .rsplit_once('/')
+                .unwrap_or(("", &member.path));
+
+            if name == OPAQUE_WHITEOUT {
+                binaries.retain(|path, _| !is_inside(path, dir));
+            } else if let Some(name) =
+                name.strip_prefix(WHITEOUT_PREFIX)
+            {
+                let deleted = format!("{dir}/{name}");
+
+                binaries.retain(|path, _| {
+                    *path != deleted && !is_inside(path, &deleted)
+                });
+            }
+        }
+
+        for member in members {
+            let name = member
+                .path
+                .rsplit_once('/')
+                .map_or(member.path.as_str(), |(_, x)| x);
+
+            if name.starts_with(WHITEOUT_PREFIX) {
+                continue;
+            }
+
+            if binary::is_x86_object(&member.data) {
+                binaries.insert(member.path, (index, member.data));
+            } else {
+                binaries.remove(&member.path);
+            }
+        }
+
+        names.push(layer_name(layer_path));
+    }
+
+    let mut result: Vec<_> = names
+        .into_iter()
+        .map(|name| Layer {
+            image: image.to_string(),
+            name,
+            members: Vec::new(),
+        })
+        .collect();
+
+    for (path, (index, data)) in binaries {
+        result[index].members.push(Member { path, data });
+    }
+
+    Ok(result)
+}
+
+/// Whether `path` is inside the directory `dir`.
+fn is_inside(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Images listed in the `manifest.json` of `docker save`, with the
+/// paths of their layers, e.g., `0123…/layer.tar` or
+/// `blobs/sha256/0123…`.  Images are named after their first tag, or
+/// after their configuration if they are untagged.
+fn docker_images(manifest: &[u8]) -> anyhow::Result<Vec<Image>> {
+    let manifest: Value = serde_json::from_slice(manifest)
+        .context("Could not parse 'manifest.json'")?;
+    let mut result = Vec::new();
+
+    for image in manifest.as_array().into_iter().flatten() {
+        let name = match image["RepoTags"][0].as_str() {
+            Some(tag) => tag.to_string(),
+            None => layer_name(image["Config"].as_str().unwrap_or("")),
+        };
+        let mut layers = Vec::new();
+
+        for layer in image["Layers"].as_array().into_iter().flatten() {
+            let layer = layer
+                .as_str()
+                .ok_or_else(|| anyhow!("Malformed 'manifest.json'"))?;
+
+            layers.push(layer.to_string());
+        }
+
+        result.push((name, layers));
+    }
+
+    Ok(result)
+}
+
+/// Images of the x86 platforms listed in the `index.json` of an OCI
+/// image layout, with the paths of their layers.  Nested image indexes
+/// are followed.
+///
+/// Images are named after the `org.opencontainers.image.ref.name`
+/// annotation of the outermost entry, or after the digest of their
+/// manifest, followed by the architecture if it is given.  Manifests
+/// that are listed more than once, e.g., under two tags, are only read
+/// once.
+fn oci_images(
+    files: &HashMap<&str, &[u8]>,
+    index: &[u8],
+) -> anyhow::Result<Vec<Image>> {
+    let index: Value = serde_json::from_slice(index)
+        .context("Could not parse 'index.json'")?;
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+
+    add_oci_images(files, &index, None, &mut seen, &mut result)?;
+
+    Ok(result)
+}
+
+fn add_oci_images(
+    files: &HashMap<&str, &[u8]>,
+    index: &Value,
+    name: Option<&str>,
+    seen: &mut HashSet<String>,
+    result: &mut Vec<Image>,
+) -> anyhow::Result<()> {
+    for entry in index["manifests"].as_array().into_iter().flatten() {
+        let architecture = entry["platform"]["architecture"].as_str();
+
+        if architecture.is_some_and(|x| !ARCHITECTURES.contains(&x)) {
+            continue;
+        }
+
+        let path = blob_path(&entry["digest"])?;
+
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+
+        let data = files.get(path.as_str()).ok_or_else(|| {
+            anyhow!("Image does not contain manifest '{path}'")
+        })?;
+        let manifest: Value = serde_json::from_slice(data)
+            .with_context(|| {
+                format!("Could not parse manifest '{path}'")
+            })?;
+        let name = name
+            .or(entry["annotations"]
+                ["org.opencontainers.image.ref.name"]
+                .as_str())
+            .map_or_else(|| layer_name(&path), str::to_string);
+
+        if manifest["manifests"].is_array() {
+            add_oci_images(
+                files,
+                &manifest,
+                Some(&name),
+                seen,
+                result,
+            )?;
+
+            continue;
+        }
+
+        let mut layers = Vec::new();
+
+        for layer in manifest["layers"].as_array().into_iter().flatten()
+        {
+            layers.push(blob_path(&layer["digest"])?);
+        }
+
+        let name = match architecture {
+            Some(architecture) => format!("{name} ({architecture})"),
+            None => name,
+        };
+
+        result.push((name, layers));
+    }
+
+    Ok(())
+}
+
+/// Turns `sha256:0123…` into `blobs/sha256/0123…`.
+fn blob_path(digest: &Value) -> anyhow::Result<String> {
+    let (algorithm, hex) = digest
+        .as_str()
+        .and_then(|x| x.split_once(':'))
+        .ok_or_else(|| anyhow!("Malformed digest in image manifest"))?;
+
+    Ok(format!("blobs/{algorithm}/{hex}"))
+}
+
+/// Shortens `blobs/sha256/0123…` to `sha256:0123456789ab`, and
+/// `0123…/layer.tar` to `0123456789ab`.
+fn layer_name(path: &str) -> String {
+    let short = |x: &str| x.chars().take(12).collect::<String>();
+
+    match path.strip_prefix("blobs/") {
+        Some(blob) => match blob.split_once('/') {
+            Some((algorithm, hex)) => {
+                format!("{algorithm}:{}", short(hex))
+            }
+            None => path.to_string(),
+        },
+        None => short(path.split('/').next().unwrap_or(path)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn whiteouts() {
+        let elf = elf();
+        let base = tar(&[
+            ("usr/bin/a", &elf),
+            ("usr/bin/b", &elf),
+            ("opt/app/c", &elf),
+            ("etc/d", &elf),
+        ]);
+        let upper = tar(&[
+            ("usr/bin/.wh.a", b""),
+            ("opt/app/.wh..wh..opq", b""),
+            ("opt/app/e", &elf),
+            ("etc/d", b"not a binary"),
+        ]);
+        let manifest =
+            br#"[{"Layers": ["base/layer.tar", "upper/layer.tar"]}]"#;
+        let files = vec![
+            Member {
+                path: "/manifest.json".to_string(),
+                data: manifest.to_vec(),
+            },
+            Member {
+                path: "/base/layer.tar".to_string(),
+                data: base,
+            },
+            Member {
+                path: "/upper/layer.tar".to_string(),
+                data: upper,
+            },
+        ];
+
+        let layers = read_layers(&files).unwrap().unwrap();
+        let paths: Vec<Vec<&str>> = layers
+            .iter()
+            .map(|layer| {
+                layer.members.iter().map(|x| x.path.as_str()).collect()
+            })
+            .collect();
+
+        assert_eq!(layers[0].name, "base");
+        assert_eq!(paths, vec![vec!["/usr/bin/b"], vec!["/opt/app/e"]]);
+    }
+
+    /// An x86-64 ELF header without sections, which is enough for
+    /// `binary::is_x86_object`.
+    fn elf() -> Vec<u8> {
+        let mut result = vec![0; 64];
+
+        result[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+        result[16] = 2; // e_type: ET_EXEC
+        result[18] = 62; // e_machine: EM_X86_64
+        result[20] = 1; // e_version
+        result[52] = 64; // e_ehsize
+
+        result
+    }
+
+    #[test]
+    fn images() {
+        let elf = elf();
+
+        assert!(binary::is_x86_object(&elf));
+
+        let base = tar(&[("usr/bin/a", &elf), ("usr/bin/b", &elf)]);
+        let upper = tar(&[("usr/bin/.wh.a", b""), ("usr/bin/c", &elf)]);
+        let other = tar(&[("usr/bin/b", b"not a binary")]);
+        let manifest = br#"[
+            {"RepoTags": ["a:1"], "Layers": ["base.tar", "upper.tar"]},
+            {"Config": "0123456789abcdef.json",
+             "Layers": ["base.tar", "other.tar"]}
+        ]"#;
+        let files = vec![
+            Member {
+                path: "/manifest.json".to_string(),
+                data: manifest.to_vec(),
+            },
+            Member {
+                path: "/base.tar".to_string(),
+                data: base,
+            },
+            Member {
+                path: "/upper.tar".to_string(),
+                data: upper,
+            },
+            Member {
+                path: "/other.tar".to_string(),
+                data: other,
+            },
+        ];
+
+        let layers = read_layers(&files).unwrap().unwrap();
+        let paths: Vec<(&str, &str, Vec<&str>)> = layers
+            .iter()
+            .map(|layer| {
+                (
+                    layer.image.as_str(),
+                    layer.name.as_str(),
+                    layer
+                        .members
+                        .iter()
+                        .map(|x| x.path.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+
+        // The whiteout and the replaced file of one image do not
+        // affect the other image.
+        //
+        assert_eq!(
+            paths,
+            vec![
+                ("a:1", "base.tar", vec!["/usr/bin/b"]),
+                ("a:1", "upper.tar", vec!["/usr/bin/c"]),
+                ("0123456789ab", "base.tar", vec!["/usr/bin/a"]),
+                ("0123456789ab", "other.tar", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn oci_index() {
+        let elf = elf();
+        let blob = |name: &str| format!("/blobs/sha256/{name}");
+        let manifest = |layer: &str| {
+            format!(r#"{{"layers": [{{"digest": "sha256:{layer}"}}]}}"#)
+                .into_bytes()
+        };
+        let index = br#"{"manifests": [{
+            "digest": "sha256:list",
+            "annotations": {"org.opencontainers.image.ref.name": "app"}
+        }]}"#;
+        let list = br#"{"manifests": [
+            {"digest": "sha256:m64",
+             "platform": {"architecture": "amd64"}},
+            {"digest": "sha256:m32",
+             "platform": {"architecture": "386"}},
+            {"digest": "sha256:arm",
+             "platform": {"architecture": "arm64"}}
+        ]}"#;
+        let files = vec![
+            Member {
+                path: "/index.json".to_string(),
+                data: index.to_vec(),
+            },
+            Member {
+                path: blob("list"),
+                data: list.to_vec(),
+            },
+            Member {
+                path: blob("m64"),
+                data: manifest("l64"),
+            },
+            Member {
+                path: blob("m32"),
+                data: manifest("l32"),
+            },
+            Member {
+                path: blob("l64"),
+                data: tar(&[("bin/a", &elf)]),
+            },
+            Member {
+                path: blob("l32"),
+                data: tar(&[("bin/.wh.a", b""), ("bin/b", &elf)]),
+            },
+        ];
+
+        let layers = read_layers(&files).unwrap().unwrap();
+        let paths: Vec<(&str, Vec<&str>)> = layers
+            .iter()
+            .map(|layer| {
+                (
+                    layer.image.as_str(),
+                    layer
+                        .members
+                        .iter()
+                        .map(|x| x.path.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("app (amd64)", vec!["/bin/a"]),
+                ("app (386)", vec!["/bin/b"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            layer_name("blobs/sha256/0123456789abcdef"),
+            "sha256:0123456789ab"
+        );
+        assert_eq!(
+            layer_name("0123456789abcdef/layer.tar"),
+            "0123456789ab"
+        );
+    }
+}
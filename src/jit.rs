@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Context};
+use goblin::elf;
+use std::collections::HashMap;
+
+/// `JiTD` in the byte order of the process that wrote the dump.
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+const JITDUMP_HEADER_SIZE: usize = 40;
+const RECORD_HEADER_SIZE: usize = 16;
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_MOVE: u32 = 1;
+
+/// A function compiled at runtime.
+pub struct JitFunction {
+    pub name: String,
+    pub address: usize,
+    pub code: Vec<u8>,
+}
+
+/// Functions in a jitdump file, in the order in which they were
+/// compiled.
+pub struct JitDump {
+    pub bitness: u32,
+    pub functions: Vec<JitFunction>,
+}
+
+/// Whether `data` starts with the header of a jitdump file, e.g.,
+/// `jit-1234.dump` written by a JVM agent, V8 with `--perf-prof`, or
+/// LuaJIT.
+pub fn is_jitdump(data: &[u8]) -> bool {
+    data.get(..4).is_some_and(|magic| {
+        let magic = magic.try_into().expect("should be 4 bytes");
+
+        u32::from_le_bytes(magic) == JITDUMP_MAGIC
+            || u32::from_be_bytes(magic) == JITDUMP_MAGIC
+    })
+}
+
+/// Reads the code and names of the functions in `JIT_CODE_LOAD`
+/// records, at the addresses given by the last `JIT_CODE_MOVE` record
+/// for each function.  Other records are skipped.
+///
+/// The format is described in `tools/perf/Documentation/jitdump-
+/// specification.txt` in the Linux source tree.
+pub fn read_jitdump(data: &[u8]) -> anyhow::Result<JitDump> {
+    let truncated = || anyhow!("Truncated jitdump file");
+    let is_big_endian = data
+        .get(..4)
+        .is_some_and(|x| x == JITDUMP_MAGIC.to_be_bytes());
+    let u32_at = |offset: usize| -> anyhow::Result<u32> {
+        let bytes =
+            data.get(offset..offset + 4).ok_or_else(truncated)?;
+        let bytes = bytes.try_into()?;
+
+        Ok(if is_big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let u64_at = |offset: usize| -> anyhow::Result<usize> {
+        let bytes =
+            data.get(offset..offset + 8).ok_or_else(truncated)?;
+        let bytes = bytes.try_into()?;
+        let value = if is_big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        };
+
+        usize::try_from(value)
+            .context("Address in jitdump file is too large")
+    };
+
+    anyhow::ensure!(is_jitdump(data), "Not a jitdump file");
+
+    let bitness = match u32_at(12)? as u16 {
+        elf::header::EM_386 => 32,
+        elf::header::EM_X86_64 => 64,
+        machine => {
+            return Err(anyhow!(
+                "Unknown instruction set architecture: {machine}"
+            ))
+        }
+    };
+    let mut functions = Vec::new();
+    // Index in `functions` by the code index, which identifies a
+    // function across records.
+    //
+    let mut indices = HashMap::new();
+    let mut offset = (u32_at(8)? as usize).max(JITDUMP_HEADER_SIZE);
+
+    while offset + RECORD_HEADER_SIZE <= data.len() {
+        let id = u32_at(offset)?;
+        let size = u32_at(offset + 4)? as usize;
+        let body = offset + RECORD_HEADER_SIZE;
+
+        anyhow::ensure!(
+            size >= RECORD_HEADER_SIZE,
+            "Malformed record in jitdump file"
+        );
+
+        match id {
+            JIT_CODE_LOAD => {
+                // pid, tid, vma, code_addr, code_size, code_index, name
+                //
+                let address = u64_at(body + 16)?;
+                let code_size = u64_at(body + 24)?;
+                let code_index = u64_at(body + 32)?;
+                let name_begin = body + 40;
+                let name_size = data
+                    .get(name_begin..offset + size)
+                    .and_then(|x| x.iter().position(|&byte| byte == 0))
+                    .ok_or_else(truncated)?;
+                let name = String::from_utf8_lossy(
+                    &data[name_begin..name_begin + name_size],
+                );
+                let code_begin = name_begin + name_size + 1;
+                let code = code_begin
+                    .checked_add(code_size)
+                    .and_then(|code_end| data.get(code_begin..code_end))
+                    .ok_or_else(truncated)?;
+
+                indices.insert(code_index, functions.len());
+                functions.push(JitFunction {
+                    name: name.into_owned(),
+                    address,
+                    code: code.to_vec(),
+                });
+            }
+            JIT_CODE_MOVE => {
+                // pid, tid, vma, old_code_addr, new_code_addr, code_size,
+                // code_index
+                //
+                let address = u64_at(body + 24)?;
+                let code_index = u64_at(body + 40)?;
+
+                if let Some(&index) = indices.get(&code_index) {
+                    functions[index].address = address;
+                }
+            }
+            _ => {}
+        }
+
+        offset += size;
+    }
+
+    Ok(JitDump { bitness, functions })
+}
+
+/// Parses a perf map such as `/tmp/perf-1234.map`, with one `START SIZE
+/// NAME` line per function.  Addresses and sizes are hexadecimal, with
+/// or without a `0x` prefix, and names can contain spaces.
+pub fn parse_perf_map(text: &str) -> Vec<(usize, usize, String)> {
+    let hex = |x: &str| {
+        usize::from_str_radix(x.strip_prefix("0x").unwrap_or(x), 16)
+            .ok()
+    };
+    let mut result = Vec::new();
+
+    for line in text.lines() {
+        let mut fields = line.trim().splitn(3, char::is_whitespace);
+        let (Some(start), Some(size), Some(name)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+
+        if let (Some(start), Some(size)) = (hex(start), hex(size)) {
+            result.push((start, size, name.trim().to_string()));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        result.extend(id.to_le_bytes());
+        result.extend(
+            (RECORD_HEADER_SIZE as u32 + body.len() as u32)
+                .to_le_bytes(),
+        );
+        result.extend(0_u64.to_le_bytes());
+        result.extend(body);
+
+        result
+    }
+
+    #[test]
+    fn jitdump() {
+        let mut data = Vec::new();
+
+        data.extend(JITDUMP_MAGIC.to_le_bytes());
+        data.extend(1_u32.to_le_bytes());
+        data.extend((JITDUMP_HEADER_SIZE as u32).to_le_bytes());
+        data.extend(u32::from(elf::header::EM_X86_64).to_le_bytes());
+        data.resize(JITDUMP_HEADER_SIZE, 0);
+
+        let mut load = vec![0; 8];
+
+        for value in [0x1000_u64, 0x1000, 1, 7] {
+            load.extend(value.to_le_bytes());
+        }
+
+        load.extend(b"foo\0\xc3");
+        data.extend(record(JIT_CODE_LOAD, &load));
+        data.extend(record(2, &[0; 8]));
+
+        let mut code_move = vec![0; 8];
+
+        for value in [0x1000_u64, 0x1000, 0x2000, 1, 7] {
+            code_move.extend(value.to_le_bytes());
+        }
+
+        data.extend(record(JIT_CODE_MOVE, &code_move));
+
+        let dump = read_jitdump(&data).unwrap();
+
+        assert_eq!(dump.bitness, 64);
+        assert_eq!(dump.functions.len(), 1);
+        assert_eq!(dump.functions[0].name, "foo");
+        assert_eq!(dump.functions[0].address, 0x2000);
+        assert_eq!(dump.functions[0].code, vec![0xc3]);
+        assert!(read_jitdump(&data[..data.len() - 1]).is_err());
+
+        // A code size that overflows the end of the code.
+        //
+        let code_size = JITDUMP_HEADER_SIZE + RECORD_HEADER_SIZE + 24;
+
+        data[code_size..code_size + 8]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+
+        assert_eq!(
+            read_jitdump(&data).err().unwrap().to_string(),
+            "Truncated jitdump file"
+        );
+    }
+
+    #[test]
+    fn perf_map() {
+        let map = "\
+7f3c1c0004a0 80 Interpreter
+0x7f3c1c001000 0x1c LazyCompile:~add file.js:1
+garbage
+";
+
+        assert_eq!(
+            parse_perf_map(map),
+            vec![
+                (0x7f3c1c0004a0, 0x80, "Interpreter".to_string()),
+                (
+                    0x7f3c1c001000,
+                    0x1c,
+                    "LazyCompile:~add file.js:1".to_string()
+                ),
+            ]
+        );
+    }
+}
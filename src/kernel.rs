@@ -0,0 +1,366 @@
+use goblin::elf;
+use std::collections::{HashMap, HashSet};
+
+/// Functions that make the FPU and the SIMD registers usable in kernel
+/// code.  Since Linux 5.11, `kernel_fpu_begin` is an inline wrapper of
+/// `kernel_fpu_begin_mask`.
+pub const FPU_BEGIN: &[&str] =
+    &["kernel_fpu_begin", "kernel_fpu_begin_mask"];
+
+/// Appended to the name of a function to name the replacement
+/// instructions of its alternatives, e.g., `memcpy (alternative)`.
+pub const ALTERNATIVE_SUFFIX: &str = " (alternative)";
+
+const ALTINSTRUCTIONS: &str = ".altinstructions";
+const ALTINSTR_REPLACEMENT: &str = ".altinstr_replacement";
+
+/// Sections that only occur in `vmlinux` and in loadable modules.
+const KERNEL_SECTIONS: &[&str] =
+    &[".modinfo", "__ksymtab", "__ksymtab_gpl", ALTINSTRUCTIONS];
+
+/// Layouts of `struct alt_instr` in different versions of Linux, as the
+/// size of an entry and the offset of `replacementlen` in it.  All of
+/// them start with the offsets of the original and the replacement
+/// instructions relative to the fields themselves.
+///
+/// - Since 5.19: `u32 ft_flags; u8 instrlen; u8 replacementlen;`
+/// - Before 5.10: `u16 cpuid; u8 instrlen; u8 replacementlen;
+///   u8 padlen;`
+/// - In between: `u16 cpuid; u8 instrlen; u8 replacementlen;`
+const ALT_INSTR_LAYOUTS: &[(usize, usize)] =
+    &[(14, 13), (13, 11), (12, 11)];
+
+/// Relocation types of `call` instructions to functions in other
+/// sections or modules.  They have the same values for i386
+/// (`R_386_PC32` and `R_386_PLT32`) and x86-64 (`R_X86_64_PC32` and
+/// `R_X86_64_PLT32`).
+const CALL_RELOCATIONS: &[u32] = &[2, 4];
+
+/// Instructions that the kernel patches over the instructions at `site`
+/// at boot if the CPU supports a given feature, e.g., to use `rep movsb`
+/// or AVX in place of a generic implementation.
+#[derive(Debug, PartialEq)]
+pub struct Alternative {
+    pub site: usize,
+    pub replacement: usize,
+    pub length: usize,
+}
+
+/// Whether `elf` is a Linux kernel image (`vmlinux`) or a loadable
+/// module.
+pub fn is_kernel(elf: &elf::Elf) -> bool {
+    elf.section_headers.iter().any(|section_hdr| {
+        elf.shdr_strtab
+            .get_at(section_hdr.sh_name)
+            .is_some_and(|name| KERNEL_SECTIONS.contains(&name))
+    })
+}
+
+/// Whether a section is loaded into memory and executable, e.g.,
+/// `.init.text` or `.altinstr_replacement`.
+pub fn is_code(section_hdr: &elf::SectionHeader) -> bool {
+    let flags = u64::from(elf::section_header::SHF_ALLOC)
+        | u64::from(elf::section_header::SHF_EXECINSTR);
+
+    section_hdr.sh_flags & flags == flags
+}
+
+/// Returns the contents of a section, or `None` if its offset and size
+/// are out of bounds or overflow.
+fn section_bytes<'a>(
+    data: &'a [u8],
+    section_hdr: &elf::SectionHeader,
+) -> Option<&'a [u8]> {
+    let begin = usize::try_from(section_hdr.sh_offset).ok()?;
+    let end = begin
+        .checked_add(usize::try_from(section_hdr.sh_size).ok()?)?;
+
+    data.get(begin..end)
+}
+
+/// Reads the alternatives in `.altinstructions`.  `addresses` maps the
+/// indexes of the code sections to their addresses, which are assigned
+/// by the caller in relocatable objects.
+pub fn alternatives(
+    elf: &elf::Elf,
+    data: &[u8],
+    addresses: &HashMap<usize, usize>,
+) -> Vec<Alternative> {
+    let Some((index, section_hdr)) =
+        elf.section_headers.iter().enumerate().find(|(_, x)| {
+            elf.shdr_strtab.get_at(x.sh_name) == Some(ALTINSTRUCTIONS)
+        })
+    else {
+        return Vec::new();
+    };
+    let Some(entries) = section_bytes(data, section_hdr) else {
+        return Vec::new();
+    };
+
+    // Replacements have to be inside `.altinstr_replacement` and sites
+    // inside any other code section.
+    //
+    let mut replacement_range = 0..0;
+    let mut code_ranges = Vec::new();
+
+    for (index, &address) in addresses.iter() {
+        let section_hdr = &elf.section_headers[*index];
+        let Some(end) =
+            address.checked_add(section_hdr.sh_size as usize)
+        else {
+            continue;
+        };
+        let range = address..end;
+
+        if elf.shdr_strtab.get_at(section_hdr.sh_name)
+            == Some(ALTINSTR_REPLACEMENT)
+        {
+            replacement_range = range;
+        } else {
+            code_ranges.push(range);
+        }
+    }
+
+    let is_valid = |alternative: &Alternative| {
+        code_ranges
+            .iter()
+            .any(|range| range.contains(&alternative.site))
+            && (alternative.length == 0
+                || (replacement_range
+                    .contains(&alternative.replacement)
+                    && alternative
+                        .replacement
+                        .checked_add(alternative.length)
+                        .is_some_and(|end| {
+                            end <= replacement_range.end
+                        })))
+    };
+
+    // In relocatable objects, the offsets are filled in by relocations
+    // against the sections that contain the instructions.
+    //
+    if elf.header.e_type == elf::header::ET_REL {
+        let targets = relocation_targets(elf, index, addresses);
+
+        read_alternatives(
+            entries,
+            |offset| targets.get(&offset).copied(),
+            is_valid,
+        )
+    } else {
+        let address = section_hdr.sh_addr as usize;
+
+        read_alternatives(
+            entries,
+            |offset| {
+                let bytes = entries.get(offset..offset + 4)?;
+                let relative =
+                    i32::from_le_bytes(bytes.try_into().ok()?);
+
+                address
+                    .checked_add(offset)?
+                    .checked_add_signed(relative as isize)
+            },
+            is_valid,
+        )
+    }
+}
+
+/// Decodes `entries` with the first layout of `struct alt_instr` for
+/// which all alternatives are valid.  `target` resolves the relative
+/// offset at an offset in `entries`.
+fn read_alternatives(
+    entries: &[u8],
+    target: impl Fn(usize) -> Option<usize>,
+    is_valid: impl Fn(&Alternative) -> bool,
+) -> Vec<Alternative> {
+    'layouts: for &(size, length_offset) in ALT_INSTR_LAYOUTS {
+        if entries.is_empty() || !entries.len().is_multiple_of(size) {
+            continue;
+        }
+
+        let mut result = Vec::new();
+
+        for offset in (0..entries.len()).step_by(size) {
+            let alternative = match (target(offset), target(offset + 4))
+            {
+                (Some(site), Some(replacement)) => Alternative {
+                    site,
+                    replacement,
+                    length: entries[offset + length_offset] as usize,
+                },
+                _ => continue 'layouts,
+            };
+
+            if !is_valid(&alternative) {
+                continue 'layouts;
+            }
+
+            result.push(alternative);
+        }
+
+        return result;
+    }
+
+    Vec::new()
+}
+
+/// Addresses of the operands of `call` instructions in a relocatable
+/// object that are relocated against one of `FPU_BEGIN`.
+pub fn fpu_calls(
+    elf: &elf::Elf,
+    addresses: &HashMap<usize, usize>,
+) -> HashSet<usize> {
+    let mut result = HashSet::new();
+
+    for (index, relocations) in elf.shdr_relocs.iter() {
+        let target = elf.section_headers[*index].sh_info as usize;
+        let Some(&address) = addresses.get(&target) else {
+            continue;
+        };
+
+        for relocation in relocations.iter() {
+            let is_fpu_begin = elf
+                .syms
+                .get(relocation.r_sym)
+                .and_then(|sym| elf.strtab.get_at(sym.st_name))
+                .is_some_and(|name| FPU_BEGIN.contains(&name));
+
+            if !is_fpu_begin
+                || !CALL_RELOCATIONS.contains(&relocation.r_type)
+            {
+                continue;
+            }
+
+            if let Some(call) =
+                address.checked_add(relocation.r_offset as usize)
+            {
+                result.insert(call);
+            }
+        }
+    }
+
+    result
+}
+
+/// Maps the offsets in the section at `index` to the addresses that
+/// the relocations at these offsets refer to.
+fn relocation_targets(
+    elf: &elf::Elf,
+    index: usize,
+    addresses: &HashMap<usize, usize>,
+) -> HashMap<usize, usize> {
+    let mut result = HashMap::new();
+
+    for (relocation_index, relocations) in elf.shdr_relocs.iter() {
+        if elf.section_headers[*relocation_index].sh_info as usize
+            != index
+        {
+            continue;
+        }
+
+        for relocation in relocations.iter() {
+            let Some(sym) = elf.syms.get(relocation.r_sym) else {
+                continue;
+            };
+            let Some(&address) = addresses.get(&sym.st_shndx) else {
+                continue;
+            };
+            let target = (address as i64)
+                .wrapping_add(sym.st_value as i64)
+                .wrapping_add(relocation.r_addend.unwrap_or_default());
+
+            result
+                .insert(relocation.r_offset as usize, target as usize);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An entry of `.altinstructions` in the layout used since Linux
+    /// 5.19, at `address`.
+    fn entry(
+        address: usize,
+        site: usize,
+        replacement: usize,
+    ) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        result
+            .extend((site.wrapping_sub(address) as i32).to_le_bytes());
+        result.extend(
+            (replacement.wrapping_sub(address + 4) as i32)
+                .to_le_bytes(),
+        );
+        result.extend(0x0001_0093_u32.to_le_bytes());
+        result.extend([5, 3]);
+
+        result
+    }
+
+    #[test]
+    fn section_bounds() {
+        let data = [0u8; 16];
+        let section_hdr =
+            |sh_offset: u64, sh_size: u64| elf::SectionHeader {
+                sh_offset,
+                sh_size,
+                ..Default::default()
+            };
+
+        assert_eq!(
+            section_bytes(&data, &section_hdr(4, 12)).map(<[u8]>::len),
+            Some(12)
+        );
+        assert!(section_bytes(&data, &section_hdr(4, 13)).is_none());
+        assert!(
+            section_bytes(&data, &section_hdr(4, u64::MAX)).is_none()
+        );
+    }
+
+    #[test]
+    fn layouts() {
+        let entries: Vec<u8> = [
+            entry(0x3000, 0x1000, 0x2000),
+            entry(0x300e, 0x1040, 0x2003),
+        ]
+        .concat();
+        let target = |offset: usize| {
+            let bytes = entries.get(offset..offset + 4)?;
+            let relative = i32::from_le_bytes(bytes.try_into().ok()?);
+
+            (0x3000 + offset).checked_add_signed(relative as isize)
+        };
+        let is_valid = |alternative: &Alternative| {
+            (0x1000..0x1100).contains(&alternative.site)
+                && (0x2000..0x2010).contains(&alternative.replacement)
+        };
+
+        // 28 bytes can only hold entries of the 14-byte layout.
+        //
+        assert_eq!(
+            read_alternatives(&entries, target, is_valid),
+            vec![
+                Alternative {
+                    site: 0x1000,
+                    replacement: 0x2000,
+                    length: 3,
+                },
+                Alternative {
+                    site: 0x1040,
+                    replacement: 0x2003,
+                    length: 3,
+                },
+            ]
+        );
+        assert_eq!(
+            read_alternatives(&entries, target, |_| false),
+            Vec::new()
+        );
+    }
+}
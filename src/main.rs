@@ -1,26 +1,715 @@
+use anyhow::{anyhow, Context};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
 use crate::binary::Binary;
-use crate::cli::OutputFormat;
+use crate::cli::{Args, OutputFormat, ShowSymbols};
+use crate::command::{
+    ContainerKind, Features, Metadata, PrintOptions, Report,
+    SymbolFilter,
+};
+use crate::package::{Member, PackageKind};
+use crate::scan::{InputKind, ScanOptions};
 
 mod binary;
 mod cli;
 mod command;
+mod compression;
+mod deps;
+mod family;
+mod image;
+mod jit;
+mod kernel;
+mod package;
+mod process;
+mod profile;
+mod scan;
 
 fn main() -> anyhow::Result<()> {
-    // TODO Also support symbols in `.dynsym` so that shared libraries
-    // can be inspected, too.
     let args = cli::Args::parse();
-    let binary = Binary::from_file(&args.binary_filename)?;
-    let features = command::get_features(
-        &binary,
+
+    if let Some(pid) = args.pid {
+        return print_report(&args, &get_process_report(&args, pid)?);
+    }
+
+    let stdin = if args.binary_filenames[0] == cli::STDIN {
+        let mut data = Vec::new();
+
+        std::io::stdin()
+            .read_to_end(&mut data)
+            .context("Could not read from stdin")?;
+
+        Some(data)
+    } else {
+        None
+    };
+
+    if is_report(&args, stdin.as_deref()) {
+        if args.vector_usage
+            || args.check_kernel_fpu
+            || args.profile.is_some()
+        {
+            return Err(anyhow!(
+                "--vector-usage, --check-kernel-fpu, and --profile \
+                 require a single binary"
+            ));
+        }
+
+        let report = match stdin {
+            Some(data) => {
+                let mut report = Report::default();
+
+                add_package(&mut report, "stdin", &data, &args)?;
+
+                report
+            }
+            None => get_report(&args)?,
+        };
+
+        return print_report(&args, &report);
+    }
+
+    let data = match stdin {
+        Some(data) => data,
+        None => std::fs::read(&args.binary_filenames[0])?,
+    };
+    let binary = if args.raw {
+        let symbols = match &args.symbol_map {
+            Some(path) => binary::parse_symbol_map(
+                &std::fs::read_to_string(path)?,
+            ),
+            None => Vec::new(),
+        };
+        let mut binary = Binary::from_raw(
+            binary::raw_slice(&data, args.offset, args.length)?,
+            args.base_address,
+            args.bitness.unwrap_or(64),
+            &symbols,
+            args.decoder_options,
+            &args.bitness_overrides,
+        )?;
+
+        if let Some(path) = &args.perf_map {
+            binary.add_symbols(&jit::parse_perf_map(
+                &std::fs::read_to_string(path)?,
+            ));
+        }
+
+        binary
+    } else {
+        Binary::from_bytes(
+            &data,
+            args.decoder_options,
+            &args.bitness_overrides,
+        )?
+    };
+    let metadata = Metadata::new(&binary);
+
+    if args.vector_usage {
+        let summary = command::get_vector_summary(
+            &binary,
+            &symbol_filter(&args),
+            args.show_symbols,
+        )?;
+
+        return match args.output_format {
+            OutputFormat::List => {
+                command::print_vector_summary_list(&summary)
+            }
+            OutputFormat::Table => {
+                command::print_vector_summary_table(&summary)?;
+                command::print_regions_table(&metadata)
+            }
+            OutputFormat::Json => command::print_vector_summary_json(
+                &args.binary_filenames[0],
+                &summary,
+                &get_features(&binary, &args)?,
+                &metadata,
+                &print_options(&args),
+            ),
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                unreachable!(
+                    "--csv and --tsv conflict with --vector-usage"
+                )
+            }
+        };
+    }
+
+    if args.check_kernel_fpu {
+        let data = command::get_unguarded_simd(
+            &binary,
+            &symbol_filter(&args),
+            args.show_symbols,
+        )?;
+
+        return match args.output_format {
+            OutputFormat::List => {
+                command::print_unguarded_simd_list(&data)
+            }
+            OutputFormat::Table => {
+                command::print_unguarded_simd_table(&data)?;
+                command::print_regions_table(&metadata)
+            }
+            OutputFormat::Json => command::print_unguarded_simd_json(
+                &args.binary_filenames[0],
+                &data,
+                &get_features(&binary, &args)?,
+                &metadata,
+                &print_options(&args),
+            ),
+            OutputFormat::Csv | OutputFormat::Tsv => unreachable!(
+                "--csv and --tsv conflict with --check-kernel-fpu"
+            ),
+        };
+    }
+
+    if let Some(path) = &args.profile {
+        let decompressed = compression::decompress(&data)?;
+        let profile = profile::read_profile(
+            Path::new(path),
+            Path::new(&args.binary_filenames[0]),
+            decompressed.as_deref().unwrap_or(&data),
+            &binary,
+        )?;
+        let features = command::get_features(
+            &binary,
+            &args.feature_filter,
+            &symbol_filter(&args),
+            args.show_symbols,
+            args.group_by_encoding,
+            args.group_by_family,
+            Some(&profile.samples),
+        )?;
+        let shares = command::get_sample_shares(
+            &features,
+            profile.in_binary(),
+            profile.total,
+        );
+
+        return match args.output_format {
+            OutputFormat::List => {
+                command::print_sample_shares_list(&shares)
+            }
+            OutputFormat::Table => {
+                command::print_sample_shares_table(&shares)?;
+                command::print_regions_table(&metadata)
+            }
+            OutputFormat::Json => command::print_sample_shares_json(
+                &args.binary_filenames[0],
+                &shares,
+                &features,
+                &metadata,
+                &print_options(&args),
+            ),
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                unreachable!("--csv and --tsv conflict with --profile")
+            }
+        };
+    }
+
+    let features = get_features(&binary, &args)?;
+
+    if let Some(format) = args.matrix {
+        return command::print_matrix(
+            &features,
+            format,
+            args.checkmarks,
+            &print_options(&args),
+        );
+    }
+
+    match args.output_format {
+        OutputFormat::List => {
+            command::print_list(&features, &print_options(&args))
+        }
+        OutputFormat::Table => {
+            command::print_table(&features, &print_options(&args))?;
+            command::print_regions_table(&metadata)
+        }
+        OutputFormat::Json => command::print_json(
+            &args.binary_filenames[0],
+            &features,
+            &metadata,
+            &print_options(&args),
+        ),
+        OutputFormat::Csv => command::print_delimited(
+            &args.binary_filenames[0],
+            &features,
+            ',',
+            &print_options(&args),
+        ),
+        OutputFormat::Tsv => command::print_delimited(
+            &args.binary_filenames[0],
+            &features,
+            '\t',
+            &print_options(&args),
+        ),
+    }
+}
+
+/// Whether several binaries are inspected and their features are
+/// combined into one report.  `stdin` holds the input if it is read
+/// from stdin.
+fn is_report(args: &Args, stdin: Option<&[u8]>) -> bool {
+    let path = Path::new(&args.binary_filenames[0]);
+    let is_package = match stdin {
+        Some(data) => compression::decompress(data)
+            .ok()
+            .flatten()
+            .as_deref()
+            .or(Some(data))
+            .and_then(package::package_kind)
+            .is_some(),
+        None => matches!(
+            scan::input_kind(path),
+            Some(InputKind::Package(_))
+        ),
+    };
+
+    !args.raw
+        && (args.with_deps
+            || args.binary_filenames.len() > 1
+            || path.is_dir()
+            || is_package)
+}
+
+fn get_report(args: &Args) -> anyhow::Result<Report> {
+    let inputs = scan::find_inputs(
+        &args.binary_filenames,
+        &ScanOptions {
+            include: &args.include,
+            exclude: &args.exclude,
+            follow_symlinks: args.follow_symlinks,
+        },
+    )?;
+    let sysroot = args.sysroot.as_deref().map(Path::new);
+    let mut seen = HashSet::new();
+    let mut report = Report::default();
+
+    for (path, kind) in inputs {
+        if let InputKind::Package(_) = kind {
+            add_package(
+                &mut report,
+                &path.display().to_string(),
+                &std::fs::read(&path)?,
+                args,
+            )?;
+
+            continue;
+        }
+
+        let deps = if args.with_deps {
+            // The binary itself is still inspected if its dependencies
+            // cannot be read, e.g., because it is not an ELF file.
+            //
+            deps::dependencies(&path, sysroot).unwrap_or_else(|error| {
+                eprintln!(
+                    "Skipping the dependencies of '{}': {error}",
+                    path.display()
+                );
+
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+
+        for path in std::iter::once(path).chain(deps) {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+
+            // Only a missing `.text` section or a malformed binary can
+            // fail here, which should not stop the whole scan.
+            //
+            let binary = match Binary::from_file(
+                &path,
+                args.decoder_options,
+                &args.bitness_overrides,
+            ) {
+                Ok(binary) => binary,
+                Err(error) => {
+                    eprintln!("Skipping '{}': {error}", path.display());
+                    continue;
+                }
+            };
+
+            // Neither should a stripped binary when symbols are
+            // requested.
+            //
+            let features = match get_features(&binary, args) {
+                Ok(features) => features,
+                Err(error) => {
+                    eprintln!("Skipping '{}': {error}", path.display());
+                    continue;
+                }
+            };
+
+            report.add(
+                path.display().to_string(),
+                features,
+                Metadata::new(&binary),
+            );
+        }
+    }
+
+    Ok(report)
+}
+
+/// Inspects the executable mappings of a running process.  Mapped files
+/// are read from disk and only the mapped part of their code is
+/// inspected.  Anonymous mappings are read from memory if requested.
+fn get_process_report(args: &Args, pid: u32) -> anyhow::Result<Report> {
+    // Code that is not an ELF image, such as JIT code, is decoded with
+    // the bitness of the program.
+    //
+    let bitness = match binary::read_header(Path::new(&format!(
+        "/proc/{pid}/exe"
+    )))
+    .and_then(|header| binary::elf_machine(&header))
+    {
+        Some(goblin::elf::header::EM_386) => 32,
+        _ => 64,
+    };
+    let perf_map = read_perf_map(args, pid)?;
+    let mut report = Report::default();
+
+    for mapping in process::executable_mappings(pid)? {
+        if !mapping.is_file() && !args.anonymous {
+            continue;
+        }
+
+        let binary = match mapping_binary(
+            &mapping, pid, bitness, &perf_map, args,
+        ) {
+            Ok(binary) => binary,
+            Err(error) => {
+                eprintln!("Skipping '{}': {error}", mapping.name());
+                continue;
+            }
+        };
+
+        // Many mapped libraries are stripped, which should not stop the
+        // inspection of the others when symbols are requested.
+        //
+        let features = match get_features(&binary, args) {
+            Ok(features) => features,
+            Err(error) => {
+                eprintln!("Skipping '{}': {error}", mapping.name());
+                continue;
+            }
+        };
+
+        report.add(mapping.name(), features, Metadata::new(&binary));
+    }
+
+    Ok(report)
+}
+
+/// Reads the code of a mapping, at the addresses at which it is
+/// mapped.  ELF images in memory such as the vDSO are read like files,
+/// other memory as raw code, named after the functions in `perf_map`.
+fn mapping_binary(
+    mapping: &process::Mapping,
+    pid: u32,
+    bitness: u32,
+    perf_map: &[(usize, usize, String)],
+    args: &Args,
+) -> anyhow::Result<Binary> {
+    let data = if mapping.is_file() {
+        std::fs::read(mapping.file_path(pid))?
+    } else {
+        process::read_memory(pid, mapping)?
+    };
+
+    if binary::elf_machine(&data).is_none() {
+        // Files such as `/memfd:jit (deleted)` or `/dev/zero (deleted)`
+        // back shared memory, which JIT compilers also write code to.
+        //
+        anyhow::ensure!(
+            args.anonymous,
+            "not an ELF file (read it from memory with --anonymous)"
+        );
+
+        let data = if mapping.is_file() {
+            process::read_memory(pid, mapping)?
+        } else {
+            data
+        };
+
+        let mut binary = Binary::from_raw(
+            &data,
+            mapping.start,
+            bitness,
+            &[],
+            args.decoder_options,
+            &args.bitness_overrides,
+        )?;
+
+        binary.add_symbols(perf_map);
+
+        return Ok(binary);
+    }
+
+    let mut binary = Binary::from_bytes(
+        &data,
+        args.decoder_options,
+        &args.bitness_overrides,
+    )?;
+
+    binary.relocate(
+        process::mapped_range(&data, mapping)?,
+        mapping.start,
+    );
+
+    Ok(binary)
+}
+
+/// Reads the perf map given with `--perf-map`, or the one that the JIT
+/// compiler of the process writes to `/tmp/perf-<PID>.map` if it
+/// exists.  The process can see another `/tmp` than this program.
+fn read_perf_map(
+    args: &Args,
+    pid: u32,
+) -> anyhow::Result<Vec<(usize, usize, String)>> {
+    let path = match &args.perf_map {
+        Some(path) => Some(PathBuf::from(path)),
+        None => [
+            format!("/proc/{pid}/root/tmp/perf-{pid}.map"),
+            format!("/tmp/perf-{pid}.map"),
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists()),
+    };
+
+    Ok(match path {
+        Some(path) => jit::parse_perf_map(
+            &std::fs::read_to_string(&path).with_context(|| {
+                format!("Could not read '{}'", path.display())
+            })?,
+        ),
+        None => Vec::new(),
+    })
+}
+
+/// Adds the binaries in a package, or in each layer of a container
+/// image, to `report`.  The package may be compressed.
+fn add_package(
+    report: &mut Report,
+    container: &str,
+    data: &[u8],
+    args: &Args,
+) -> anyhow::Result<()> {
+    let decompressed = compression::decompress(data)?;
+    let data = decompressed.as_deref().unwrap_or(data);
+    let kind = package::package_kind(data)
+        .ok_or_else(|| anyhow!("'{container}' is not a package"))?;
+    let files = package::read_files(kind, data)
+        .with_context(|| format!("Could not read '{container}'"))?;
+
+    match image::read_layers(&files)? {
+        Some(layers) if kind == PackageKind::Tar => {
+            for layer in layers {
+                add_members(
+                    report,
+                    ContainerKind::Layer,
+                    &format!(
+                        "{container}:{}:{}",
+                        layer.image, layer.name
+                    ),
+                    layer.members,
+                    args,
+                )?;
+            }
+
+            Ok(())
+        }
+        _ => add_members(
+            report,
+            ContainerKind::Package,
+            container,
+            files
+                .into_iter()
+                .filter(|x| binary::is_x86_object(&x.data))
+                .collect(),
+            args,
+        ),
+    }
+}
+
+/// Adds the binaries extracted from a package or an image layer to
+/// `report`.
+fn add_members(
+    report: &mut Report,
+    kind: ContainerKind,
+    container: &str,
+    members: Vec<Member>,
+    args: &Args,
+) -> anyhow::Result<()> {
+    for member in members {
+        let binary = match Binary::from_bytes(
+            &member.data,
+            args.decoder_options,
+            &args.bitness_overrides,
+        ) {
+            Ok(binary) => binary,
+            Err(error) => {
+                eprintln!(
+                    "Skipping '{container}:{}': {error}",
+                    member.path
+                );
+                continue;
+            }
+        };
+        let features = match get_features(&binary, args) {
+            Ok(features) => features,
+            Err(error) => {
+                eprintln!(
+                    "Skipping '{container}:{}': {error}",
+                    member.path
+                );
+                continue;
+            }
+        };
+
+        report.add_to_container(
+            kind,
+            container,
+            &member.path,
+            features,
+            Metadata::new(&binary),
+        );
+    }
+
+    Ok(())
+}
+
+fn get_features(
+    binary: &Binary,
+    args: &Args,
+) -> anyhow::Result<Features> {
+    command::get_features(
+        binary,
         &args.feature_filter,
-        &args.raw_symbol_filter,
-        &args.demangled_symbol_filter,
+        &symbol_filter(args),
         args.show_symbols,
-    )?;
+        args.group_by_encoding,
+        args.group_by_family,
+        None,
+    )
+}
+
+fn print_report(args: &Args, report: &Report) -> anyhow::Result<()> {
+    if let Some(format) = args.matrix {
+        return command::print_report_matrix(
+            report,
+            format,
+            args.checkmarks,
+            &print_options(args),
+        );
+    }
 
     match args.output_format {
-        OutputFormat::List => command::print_list(&features),
-        OutputFormat::Table => command::print_table(&features),
-        OutputFormat::Json => command::print_json(&features),
+        OutputFormat::List => {
+            command::print_report_list(report, &print_options(args))
+        }
+        OutputFormat::Table => {
+            command::print_report_table(report, &print_options(args))
+        }
+        OutputFormat::Json => {
+            command::print_report_json(report, &print_options(args))
+        }
+        OutputFormat::Csv => command::print_report_delimited(
+            report,
+            ',',
+            &print_options(args),
+        ),
+        OutputFormat::Tsv => command::print_report_delimited(
+            report,
+            '\t',
+            &print_options(args),
+        ),
+    }
+}
+
+fn print_options(args: &Args) -> PrintOptions {
+    PrintOptions {
+        sizes: args.sizes,
+        sort_by: args.sort_by,
+        reverse: args.reverse,
+        top: args.top,
+        summary: args.summary,
+        legacy_json: args.legacy_json,
+        settings: settings(args),
+    }
+}
+
+/// Options that affect the output, recorded in versioned JSON output.
+fn settings(args: &Args) -> serde_json::Value {
+    let patterns = |filter: &[wildmatch::WildMatch]| -> Vec<String> {
+        filter.iter().map(ToString::to_string).collect()
+    };
+    let mut group_by = Vec::new();
+
+    if args.group_by_encoding {
+        group_by.push("encoding");
+    }
+
+    if args.group_by_family {
+        group_by.push("family");
+    }
+
+    serde_json::json!({
+        "show_symbols": match args.show_symbols {
+            ShowSymbols::No => None,
+            ShowSymbols::Raw => Some("raw"),
+            ShowSymbols::Demangled => Some("demangled"),
+        },
+        "feature_filter": patterns(&args.feature_filter),
+        "raw_symbol_filter": patterns(&args.raw_symbol_filter),
+        "demangled_symbol_filter": patterns(&args.demangled_symbol_filter),
+        "group_by": group_by,
+        "zmm_only": args.zmm_only,
+        "exported_only": args.exported_only,
+        "include": patterns(&args.include),
+        "exclude": patterns(&args.exclude),
+        "raw": args.raw,
+        "offset": args.offset,
+        "length": args.length,
+        "base_address": args.base_address,
+        "symbol_map": args.symbol_map,
+        "bitness": args.bitness,
+        "bitness_overrides": args
+            .bitness_overrides
+            .iter()
+            .map(|(name, bits)| {
+                serde_json::json!({ "name": name, "bitness": bits })
+            })
+            .collect::<Vec<_>>(),
+        "with_deps": args.with_deps,
+        "sysroot": args.sysroot,
+        "pid": args.pid,
+        "anonymous": args.anonymous,
+        "perf_map": args.perf_map,
+        "profile": args.profile,
+        "vector_usage": args.vector_usage,
+        "check_kernel_fpu": args.check_kernel_fpu,
+        "matrix": args.matrix.map(command::lowercase),
+        "sizes": args.sizes,
+        "summary": args.summary,
+        "sort_by": command::lowercase(args.sort_by),
+        "reverse": args.reverse,
+        "top": args.top,
+    })
+}
+
+fn symbol_filter(args: &Args) -> SymbolFilter<'_> {
+    SymbolFilter {
+        raw: &args.raw_symbol_filter,
+        demangled: &args.demangled_symbol_filter,
+        zmm_only: args.zmm_only,
+        exported_only: args.exported_only,
     }
 }
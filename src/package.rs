@@ -0,0 +1,367 @@
+use anyhow::{anyhow, Context};
+use std::io::Read;
+
+use crate::compression;
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+const RPM_MAGIC: &[u8] = &[0xed, 0xab, 0xee, 0xdb];
+const RPM_LEAD_SIZE: usize = 96;
+const RPM_HEADER_MAGIC: &[u8] = &[0x8e, 0xad, 0xe8, 0x01];
+const CPIO_MAGICS: &[&[u8]] = &[b"070701", b"070702"];
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+/// Package formats that can be inspected without installing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageKind {
+    /// Debian package: an ar(1) archive with a `data.tar` member that
+    /// is optionally compressed with gzip, xz, or zstd.
+    Deb,
+    /// RPM package: a lead and two headers followed by a compressed
+    /// cpio(1) archive in the "newc" format.
+    Rpm,
+    /// Zip archive, e.g., a Python wheel, a Java archive with native
+    /// libraries, or an Android package.
+    Zip,
+    /// Uncompressed tar archive, e.g., a container image saved by
+    /// `docker save`.
+    Tar,
+}
+
+/// A file extracted from a package.
+pub struct Member {
+    /// Path of the file inside the package, e.g., `/usr/bin/ls`.
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// Detects the package format of `data` from its first bytes.
+pub fn package_kind(data: &[u8]) -> Option<PackageKind> {
+    if data.starts_with(AR_MAGIC)
+        && data[AR_MAGIC.len()..].starts_with(b"debian-binary")
+    {
+        Some(PackageKind::Deb)
+    } else if data.starts_with(RPM_MAGIC) {
+        Some(PackageKind::Rpm)
+    } else if data.starts_with(ZIP_MAGIC) {
+        Some(PackageKind::Zip)
+    } else if data
+        .get(TAR_MAGIC_OFFSET..)
+        .is_some_and(|x| x.starts_with(TAR_MAGIC))
+    {
+        Some(PackageKind::Tar)
+    } else {
+        None
+    }
+}
+
+/// Extracts all regular files from a package in memory.  Callers pick
+/// the binaries with `binary::is_x86_object`.
+pub fn read_files(
+    kind: PackageKind,
+    data: &[u8],
+) -> anyhow::Result<Vec<Member>> {
+    match kind {
+        PackageKind::Deb => read_deb(data),
+        PackageKind::Rpm => read_rpm(data),
+        PackageKind::Zip => read_zip(data),
+        PackageKind::Tar => read_tar(data),
+    }
+}
+
+fn read_deb(data: &[u8]) -> anyhow::Result<Vec<Member>> {
+    let mut offset = AR_MAGIC.len();
+
+    while offset + AR_HEADER_SIZE <= data.len() {
+        let header = &data[offset..offset + AR_HEADER_SIZE];
+        let name = String::from_utf8_lossy(&header[..16]);
+        let size: usize = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse()
+            .context("Malformed ar header in Debian package")?;
+        let begin = offset + AR_HEADER_SIZE;
+        let content = data
+            .get(begin..begin + size)
+            .ok_or_else(|| anyhow!("Truncated Debian package"))?;
+
+        // GNU ar terminates names with a slash.
+        //
+        if name
+            .trim_end()
+            .trim_end_matches('/')
+            .starts_with("data.tar")
+        {
+            let tar = compression::decompress(content)?;
+
+            return read_tar(tar.as_deref().unwrap_or(content));
+        }
+
+        // Members are aligned to two bytes.
+        //
+        offset = begin + size + size % 2;
+    }
+
+    Err(anyhow!("Debian package does not contain 'data.tar'"))
+}
+
+/// Reads the regular files in a tar archive.  Hard links are read as
+/// copies of the file that they link to.
+pub fn read_tar(data: &[u8]) -> anyhow::Result<Vec<Member>> {
+    let mut result: Vec<Member> = Vec::new();
+
+    for entry in tar::Archive::new(data).entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        let path = normalize_path(&entry.path()?.to_string_lossy());
+
+        if entry_type.is_hard_link() {
+            let Some(target) = entry.link_name()? else {
+                continue;
+            };
+            let target = normalize_path(&target.to_string_lossy());
+
+            if let Some(member) =
+                result.iter().find(|x| x.path == target)
+            {
+                let data = member.data.clone();
+
+                result.push(Member { path, data });
+            }
+        } else if entry_type.is_file() {
+            let mut data = Vec::new();
+
+            entry.read_to_end(&mut data)?;
+            result.push(Member { path, data });
+        }
+    }
+
+    Ok(result)
+}
+
+fn read_rpm(data: &[u8]) -> anyhow::Result<Vec<Member>> {
+    // The signature header is padded to a multiple of eight bytes, the
+    // main header is not.
+    //
+    let signature_end = rpm_header_end(data, RPM_LEAD_SIZE)?;
+    let header_end =
+        rpm_header_end(data, signature_end.next_multiple_of(8))?;
+    let payload = data
+        .get(header_end..)
+        .ok_or_else(|| anyhow!("Truncated RPM header"))?;
+    let cpio = compression::decompress(payload)?;
+
+    read_cpio(cpio.as_deref().unwrap_or(payload))
+}
+
+/// Returns the offset at which the RPM header at `offset` ends.
+fn rpm_header_end(data: &[u8], offset: usize) -> anyhow::Result<usize> {
+    let truncated = || anyhow!("Truncated RPM header");
+    let read_u32 = |offset: usize| -> anyhow::Result<usize> {
+        let bytes = offset
+            .checked_add(4)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(truncated)?;
+
+        Ok(u32::from_be_bytes(bytes.try_into()?) as usize)
+    };
+
+    if !data[offset.min(data.len())..].starts_with(RPM_HEADER_MAGIC) {
+        return Err(anyhow!("Malformed RPM header"));
+    }
+
+    let index_count = read_u32(offset + 8)?;
+    let store_size = read_u32(offset + 12)?;
+
+    index_count
+        .checked_mul(16)
+        .and_then(|x| x.checked_add(offset + 16))
+        .and_then(|x| x.checked_add(store_size))
+        .ok_or_else(truncated)
+}
+
+/// Reads the regular files in a cpio archive in the "newc" format.
+fn read_cpio(data: &[u8]) -> anyhow::Result<Vec<Member>> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let header = data
+            .get(offset..offset + CPIO_HEADER_SIZE)
+            .ok_or_else(|| anyhow!("Truncated cpio archive"))?;
+
+        if !CPIO_MAGICS.iter().any(|&x| header.starts_with(x)) {
+            return Err(anyhow!("Unsupported cpio archive format"));
+        }
+
+        let field = |index: usize| -> anyhow::Result<usize> {
+            let begin = 6 + 8 * index;
+            let value = std::str::from_utf8(&header[begin..begin + 8])?;
+
+            Ok(usize::from_str_radix(value, 16)?)
+        };
+        let mode = field(1)?;
+        let size = field(6)?;
+        let name_size = field(11)?;
+        let name_begin = offset + CPIO_HEADER_SIZE;
+        let name = data
+            .get(name_begin..name_begin + name_size)
+            .ok_or_else(|| anyhow!("Truncated cpio archive"))?;
+        let name = String::from_utf8_lossy(name);
+        let name = name.trim_end_matches('\0');
+
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        // Both the name and the data are padded to four bytes.
+        //
+        let data_begin = (name_begin + name_size).next_multiple_of(4);
+        let content = data
+            .get(data_begin..data_begin + size)
+            .ok_or_else(|| anyhow!("Truncated cpio archive"))?;
+
+        if mode & 0o170000 == 0o100000 {
+            result.push(Member {
+                path: normalize_path(name),
+                data: content.to_vec(),
+            });
+        }
+
+        offset = (data_begin + size).next_multiple_of(4);
+    }
+
+    Ok(result)
+}
+
+/// Reads the files in a zip archive.  Unlike files in Debian and RPM
+/// packages, their paths are kept relative to the root of the archive,
+/// e.g., `numpy/_core/_multiarray_umath.so`.
+fn read_zip(data: &[u8]) -> anyhow::Result<Vec<Member>> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))?;
+    let mut result = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+
+        if !file.is_file() {
+            continue;
+        }
+
+        let path = file.name().to_string();
+        let mut data = Vec::new();
+
+        file.read_to_end(&mut data)
+            .with_context(|| format!("Could not extract '{path}'"))?;
+        result.push(Member { path, data });
+    }
+
+    Ok(result)
+}
+
+/// Turns `./usr/bin/ls` and `usr/bin/ls` into `/usr/bin/ls`.
+fn normalize_path(path: &str) -> String {
+    format!(
+        "/{}",
+        path.trim_start_matches("./").trim_start_matches('/')
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cpio_entry(name: &str, mode: usize, data: &[u8]) -> Vec<u8> {
+        let mut result = format!(
+            "070701{:08x}{mode:08x}{:08x}{:08x}{:08x}{:08x}{:08x}\
+             {:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0,
+            0,
+            0,
+            1,
+            0,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0,
+        )
+        .into_bytes();
+
+        result.extend_from_slice(name.as_bytes());
+        result.push(0);
+        result.resize(result.len().next_multiple_of(4), 0);
+        result.extend_from_slice(data);
+        result.resize(result.len().next_multiple_of(4), 0);
+
+        result
+    }
+
+    #[test]
+    fn cpio() {
+        let mut data = Vec::new();
+
+        data.extend(cpio_entry("./usr/bin", 0o040755, b""));
+        data.extend(cpio_entry("./usr/bin/true", 0o100755, b"\x7fELF"));
+        data.extend(cpio_entry(CPIO_TRAILER, 0, b""));
+
+        let members = read_cpio(&data).unwrap();
+
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].path, "/usr/bin/true");
+        assert_eq!(members[0].data, b"\x7fELF");
+    }
+
+    #[test]
+    fn truncated_rpm() {
+        let header = |index_count: u8, store_size: u8| {
+            let mut result = RPM_HEADER_MAGIC.to_vec();
+
+            result.extend([0; 4]);
+            result.extend([0, 0, 0, index_count, 0, 0, 0, store_size]);
+
+            result
+        };
+        let mut data = vec![0; RPM_LEAD_SIZE];
+
+        data.extend(header(0, 0));
+
+        assert_eq!(
+            rpm_header_end(&data, RPM_LEAD_SIZE).unwrap(),
+            RPM_LEAD_SIZE + 16
+        );
+
+        // The main header claims a store that is not there.
+        //
+        data.extend(header(1, 100));
+
+        assert!(read_rpm(&data).is_err());
+
+        data.truncate(data.len() - 2);
+
+        assert!(read_rpm(&data).is_err());
+        assert!(rpm_header_end(&data, usize::MAX - 4).is_err());
+    }
+
+    #[test]
+    fn kind() {
+        assert_eq!(
+            package_kind(b"!<arch>\ndebian-binary   "),
+            Some(PackageKind::Deb)
+        );
+        assert_eq!(package_kind(b"!<arch>\n/               "), None);
+        assert_eq!(
+            package_kind(&[0xed, 0xab, 0xee, 0xdb, 3, 0]),
+            Some(PackageKind::Rpm)
+        );
+        assert_eq!(
+            package_kind(b"PK\x03\x04\x14\x00"),
+            Some(PackageKind::Zip)
+        );
+    }
+}
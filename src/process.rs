@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Context};
+use goblin::elf;
+use std::fs::File;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+
+/// Suffix that the kernel appends to the path of a mapped file that
+/// has been deleted, e.g., after a package upgrade.
+const DELETED_SUFFIX: &str = " (deleted)";
+
+/// An executable mapping in `/proc/<pid>/maps`.
+#[derive(Debug, PartialEq)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// Offset of the mapping in the file.
+    pub offset: usize,
+    /// Path of the mapped file, a pseudo-path such as `[vdso]`, or an
+    /// empty string for anonymous memory.
+    pub path: String,
+}
+
+impl Mapping {
+    /// Whether the mapping is backed by a file.
+    pub fn is_file(&self) -> bool {
+        self.path.starts_with('/')
+    }
+
+    /// Name of the mapping in reports, in the format of
+    /// `/proc/<pid>/maps`, e.g., `7f1c2a000000-7f1c2a1c5000
+    /// /usr/lib/libc.so.6`.
+    pub fn name(&self) -> String {
+        let path = if self.path.is_empty() {
+            "[anon]"
+        } else {
+            &self.path
+        };
+
+        format!("{:x}-{:x} {path}", self.start, self.end)
+    }
+
+    /// Where the mapped file can be read, even if the process runs in
+    /// another mount namespace or the file has been deleted.
+    pub fn file_path(&self, pid: u32) -> PathBuf {
+        match self.path.strip_suffix(DELETED_SUFFIX) {
+            Some(_) => PathBuf::from(format!(
+                "/proc/{pid}/map_files/{:x}-{:x}",
+                self.start, self.end
+            )),
+            None => {
+                PathBuf::from(format!("/proc/{pid}/root{}", self.path))
+            }
+        }
+    }
+}
+
+/// Reads the executable mappings of a process.
+pub fn executable_mappings(pid: u32) -> anyhow::Result<Vec<Mapping>> {
+    let path = format!("/proc/{pid}/maps");
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Could not read '{path}'"))?;
+
+    parse_maps(&text)
+}
+
+/// Parses the executable mappings in the format of `/proc/<pid>/maps`:
+/// `START-END PERMISSIONS OFFSET DEVICE INODE [PATH]`.
+fn parse_maps(text: &str) -> anyhow::Result<Vec<Mapping>> {
+    let mut result = Vec::new();
+
+    for line in text.lines() {
+        let malformed = || anyhow!("Malformed line in maps: '{line}'");
+        let mut fields = line.splitn(6, ' ');
+        let mut field = || fields.next().ok_or_else(malformed);
+        let (start, end) =
+            field()?.split_once('-').ok_or_else(malformed)?;
+        let permissions = field()?;
+        let offset = field()?;
+        let _device = field()?;
+        let _inode = field()?;
+        // The path is padded with spaces, but can also contain them.
+        //
+        let path = fields.next().unwrap_or_default().trim_start();
+
+        if !permissions.contains('x') {
+            continue;
+        }
+
+        let hex = |x: &str| {
+            usize::from_str_radix(x, 16).map_err(|_| malformed())
+        };
+
+        result.push(Mapping {
+            start: hex(start)?,
+            end: hex(end)?,
+            offset: hex(offset)?,
+            path: path.to_string(),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Reads the memory of a mapping through `/proc/<pid>/mem`.
+pub fn read_memory(
+    pid: u32,
+    mapping: &Mapping,
+) -> anyhow::Result<Vec<u8>> {
+    let path = format!("/proc/{pid}/mem");
+    let file = File::open(&path)
+        .with_context(|| format!("Could not open '{path}'"))?;
+    let mut data = vec![0; mapping.end - mapping.start];
+
+    file.read_exact_at(&mut data, mapping.start as u64)
+        .with_context(|| {
+            format!("Could not read '{}' from '{path}'", mapping.name())
+        })?;
+
+    Ok(data)
+}
+
+/// Virtual addresses of the ELF file `data` that `mapping` covers, and
+/// thus the load bias of the file: the start of the range is loaded at
+/// `mapping.start`.
+pub fn mapped_range(
+    data: &[u8],
+    mapping: &Mapping,
+) -> anyhow::Result<Range<usize>> {
+    let elf = elf::Elf::parse(data)?;
+    let size = mapping.end - mapping.start;
+    let segment = elf
+        .program_headers
+        .iter()
+        .find(|x| {
+            x.p_type == elf::program_header::PT_LOAD
+                && x.is_executable()
+                && (x.p_offset as usize) < mapping.offset + size
+                && mapping.offset < (x.p_offset + x.p_filesz) as usize
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "No executable segment is mapped at '{}'",
+                mapping.name()
+            )
+        })?;
+
+    // The mapping starts at a page boundary, which can precede the
+    // segment.
+    //
+    let start = (segment.p_vaddr as usize + mapping.offset)
+        .wrapping_sub(segment.p_offset as usize);
+
+    Ok(start..start.wrapping_add(size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps() {
+        let text = "\
+55d4c8a00000-55d4c8a02000 r--p 00000000 fd:01 1048602                    /usr/bin/cat
+55d4c8a02000-55d4c8a07000 r-xp 00002000 fd:01 1048602                    /usr/bin/cat
+7f1c2a028000-7f1c2a1bd000 r-xp 00028000 fd:01 1050912                    /usr/lib/my lib.so (deleted)
+7f1c2a400000-7f1c2a401000 rwxp 00000000 00:00 0
+7ffd5e9f3000-7ffd5e9f5000 r-xp 00000000 00:00 0                          [vdso]
+";
+        let mappings = parse_maps(text).unwrap();
+
+        assert_eq!(
+            mappings.iter().map(Mapping::name).collect::<Vec<_>>(),
+            vec![
+                "55d4c8a02000-55d4c8a07000 /usr/bin/cat",
+                "7f1c2a028000-7f1c2a1bd000 /usr/lib/my lib.so (deleted)",
+                "7f1c2a400000-7f1c2a401000 [anon]",
+                "7ffd5e9f3000-7ffd5e9f5000 [vdso]",
+            ]
+        );
+        assert_eq!(mappings[0].offset, 0x2000);
+        assert!(mappings[1].is_file());
+        assert!(!mappings[2].is_file());
+        assert_eq!(
+            mappings[1].file_path(42),
+            PathBuf::from(
+                "/proc/42/map_files/7f1c2a028000-7f1c2a1bd000"
+            )
+        );
+        assert_eq!(
+            mappings[0].file_path(42),
+            PathBuf::from("/proc/42/root/usr/bin/cat")
+        );
+        assert!(parse_maps("garbage r-xp").is_err());
+    }
+}
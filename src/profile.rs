@@ -0,0 +1,548 @@
+use anyhow::{anyhow, Context};
+use goblin::elf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use symbolic_common::Name;
+use symbolic_demangle::{Demangle, DemangleOptions};
+
+use crate::binary::Binary;
+
+const PERF_MAGIC: &[u8] = b"PERFILE2";
+const PERF_HEADER_SIZE: usize = 104;
+const PERF_RECORD_MMAP: u32 = 1;
+const PERF_RECORD_SAMPLE: u32 = 9;
+const PERF_RECORD_MMAP2: u32 = 10;
+const PERF_SAMPLE_IP: u64 = 1 << 0;
+const PERF_SAMPLE_TID: u64 = 1 << 1;
+const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
+/// Offset of `sample_type` in `struct perf_event_attr`.
+const SAMPLE_TYPE_OFFSET: usize = 24;
+
+/// Samples of a profile that hit a binary.
+#[derive(Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Number of samples by address, in the address space of the
+    /// binary's file rather than the one of the profiled process.
+    pub samples: BTreeMap<usize, usize>,
+    /// Number of samples in the profile, including those in other
+    /// binaries and in the kernel.
+    pub total: usize,
+}
+
+impl Profile {
+    /// Number of samples that hit the binary.
+    pub fn in_binary(&self) -> usize {
+        self.samples.values().sum()
+    }
+}
+
+/// A sample in the output of `perf script`.
+#[derive(Debug, PartialEq)]
+struct ScriptSample<'a> {
+    ip: usize,
+    /// Symbol and offset, e.g., `main+0x16`, if perf could resolve it.
+    symbol: Option<(&'a str, usize)>,
+    dso: &'a str,
+}
+
+/// Reads the samples that hit the binary at `path` from a `perf.data`
+/// file or from the output of `perf script`.  Samples are matched to
+/// the binary by the file name of the mapped file.
+///
+/// In `perf.data`, sampled addresses are translated with the mappings
+/// that perf recorded for the sampled process.  In the output of `perf
+/// script`, they are translated with the symbol names that perf
+/// resolved, or with the load address implied by those symbols if perf
+/// could not resolve them.
+pub fn read_profile(
+    profile_path: &Path,
+    path: &Path,
+    data: &[u8],
+    binary: &Binary,
+) -> anyhow::Result<Profile> {
+    let profile = std::fs::read(profile_path).with_context(|| {
+        format!("Could not read '{}'", profile_path.display())
+    })?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    if profile.starts_with(PERF_MAGIC) {
+        read_perf_data(&profile, &name, data)
+    } else {
+        let text = String::from_utf8(profile).with_context(|| {
+            format!(
+                "'{}' is neither a perf.data file nor the output of \
+                 'perf script'",
+                profile_path.display()
+            )
+        })?;
+
+        Ok(read_perf_script(&text, &name, binary))
+    }
+}
+
+/// Whether `dso`, a path recorded by perf, refers to the binary named
+/// `name`.
+fn is_binary(dso: &str, name: &str) -> bool {
+    Path::new(dso.trim_end_matches(" (deleted)"))
+        .file_name()
+        .is_some_and(|x| x.to_string_lossy() == name)
+}
+
+/// Start addresses of the symbols of `binary` by their raw name, by
+/// their name without a symbol version, e.g., `memcpy` for
+/// `memcpy@@GLIBC_2.14`, and by their demangled name with and without
+/// parameters, since perf prints any of these.
+fn symbol_starts(binary: &Binary) -> HashMap<String, usize> {
+    let mut result = HashMap::new();
+
+    for (name, address) in binary.symbol_starts() {
+        let unversioned = name.split('@').next().unwrap_or(name);
+        let demangled = Name::from(unversioned);
+
+        for x in [
+            name.to_string(),
+            unversioned.to_string(),
+            demangled
+                .try_demangle(DemangleOptions::complete())
+                .to_string(),
+            demangled
+                .try_demangle(DemangleOptions::name_only())
+                .to_string(),
+        ] {
+            result.entry(x).or_insert(address);
+        }
+    }
+
+    result
+}
+
+fn read_perf_script(
+    text: &str,
+    name: &str,
+    binary: &Binary,
+) -> Profile {
+    let symbols = symbol_starts(binary);
+    let mut profile = Profile::default();
+    // Sampled addresses and their addresses in the binary if perf
+    // resolved their symbol.
+    //
+    let mut samples = Vec::new();
+    // With call graphs (`perf record -g`), a sample is a header line
+    // followed by one line per frame, the first of which is the
+    // sampled address.
+    //
+    let mut is_first_frame = false;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            is_first_frame = false;
+            continue;
+        }
+
+        let is_frame = line.starts_with(char::is_whitespace);
+
+        if is_frame && !is_first_frame {
+            continue;
+        }
+
+        let Some(sample) = parse_script_line(line) else {
+            // A header line without an address.
+            //
+            is_first_frame = !is_frame;
+            continue;
+        };
+
+        is_first_frame = false;
+        profile.total += 1;
+
+        if !is_binary(sample.dso, name) {
+            continue;
+        }
+
+        let address = sample.symbol.and_then(|(symbol, offset)| {
+            symbols.get(symbol).map(|address| address + offset)
+        });
+
+        samples.push((sample.ip, address));
+    }
+
+    // Position-independent binaries are loaded at an address that perf
+    // script does not print, so it is inferred from the samples whose
+    // symbol perf resolved.
+    //
+    let load_bias = samples
+        .iter()
+        .find_map(|&(ip, address)| Some(ip.wrapping_sub(address?)))
+        .unwrap_or(0);
+
+    for (ip, address) in samples {
+        let address = address.or_else(|| {
+            let address = ip.checked_sub(load_bias)?;
+
+            binary.contains(address).then_some(address)
+        });
+
+        if let Some(address) = address {
+            *profile.samples.entry(address).or_default() += 1;
+        }
+    }
+
+    profile
+}
+
+/// Parses the address, symbol, and binary of a sample or a frame, e.g.,
+/// `foo 1234 [000] 1.5: cycles:u: 401136 main+0x16 (/usr/bin/foo)` or
+/// `401136 main+0x16 (/usr/bin/foo)`.
+fn parse_script_line(line: &str) -> Option<ScriptSample<'_>> {
+    let line = line.trim();
+    let (rest, dso) = line.strip_suffix(')')?.rsplit_once(" (")?;
+    let tokens: Vec<_> = rest.split_whitespace().collect();
+    // The address follows the event name, which ends with a colon.
+    //
+    let index = tokens
+        .iter()
+        .rposition(|x| x.ends_with(':'))
+        .map_or(0, |x| x + 1);
+    let ip = usize::from_str_radix(tokens.get(index)?, 16).ok()?;
+    // Demangled names can contain spaces.
+    //
+    let mut symbol = rest.trim();
+
+    for _ in 0..=index {
+        symbol = symbol
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, x)| x.trim_start());
+    }
+
+    let symbol =
+        symbol.rsplit_once("+0x").and_then(|(name, offset)| {
+            Some((name, usize::from_str_radix(offset, 16).ok()?))
+        });
+
+    Some(ScriptSample { ip, symbol, dso })
+}
+
+fn read_perf_data(
+    profile: &[u8],
+    name: &str,
+    data: &[u8],
+) -> anyhow::Result<Profile> {
+    let truncated = || anyhow!("Truncated perf.data file");
+    let u32_at = |offset: usize| -> anyhow::Result<u32> {
+        let bytes =
+            profile.get(offset..offset + 4).ok_or_else(truncated)?;
+
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    };
+    let u64_at = |offset: usize| -> anyhow::Result<usize> {
+        let bytes =
+            profile.get(offset..offset + 8).ok_or_else(truncated)?;
+
+        Ok(u64::from_le_bytes(bytes.try_into()?) as usize)
+    };
+
+    anyhow::ensure!(
+        u64_at(8)? == PERF_HEADER_SIZE,
+        "perf.data files written to a pipe are not supported"
+    );
+
+    // The layout of samples is given by the first event, since perf
+    // only mixes events whose samples start with an identifier.
+    //
+    let attrs_offset = u64_at(24)?;
+    let sample_type = u64_at(attrs_offset + SAMPLE_TYPE_OFFSET)? as u64;
+    let ip_offset =
+        8 * usize::from(sample_type & PERF_SAMPLE_IDENTIFIER != 0);
+    // The process ID follows the address if it is recorded.
+    //
+    let pid_offset =
+        (sample_type & PERF_SAMPLE_TID != 0).then_some(ip_offset + 8);
+
+    anyhow::ensure!(
+        sample_type & PERF_SAMPLE_IP != 0,
+        "The perf.data file does not record sampled addresses"
+    );
+
+    let elf = elf::Elf::parse(data).ok();
+    let data_offset = u64_at(40)?;
+    let data_end = data_offset + u64_at(48)?;
+    // Process ID, start, end, and file offset of the mappings of the
+    // binary.
+    //
+    let mut mappings = Vec::new();
+    let mut result = Profile::default();
+    let mut offset = data_offset;
+
+    while offset + 8 <= data_end {
+        let kind = u32_at(offset)?;
+        let size = u32_at(offset + 4)? as usize >> 16;
+
+        anyhow::ensure!(
+            size >= 8,
+            "Malformed record in perf.data file"
+        );
+        profile.get(offset..offset + size).ok_or_else(truncated)?;
+
+        match kind {
+            PERF_RECORD_MMAP | PERF_RECORD_MMAP2 => {
+                let filename_offset =
+                    if kind == PERF_RECORD_MMAP { 40 } else { 72 };
+                let filename = profile
+                    .get(offset + filename_offset..offset + size)
+                    .ok_or_else(truncated)?;
+                let filename = String::from_utf8_lossy(
+                    filename
+                        .split(|&x| x == 0)
+                        .next()
+                        .unwrap_or_default(),
+                );
+
+                if is_binary(&filename, name) {
+                    let start = u64_at(offset + 16)?;
+
+                    mappings.push((
+                        u32_at(offset + 8)?,
+                        start,
+                        start + u64_at(offset + 24)?,
+                        u64_at(offset + 32)?,
+                    ));
+                }
+            }
+            PERF_RECORD_SAMPLE => {
+                let ip = u64_at(offset + 8 + ip_offset)?;
+                let pid = pid_offset
+                    .map(|x| u32_at(offset + 8 + x))
+                    .transpose()?;
+
+                result.total += 1;
+
+                let address = mappings
+                    .iter()
+                    .rev()
+                    .find(|&&(mapping_pid, start, end, _)| {
+                        pid.is_none_or(|x| x == mapping_pid)
+                            && start <= ip
+                            && ip < end
+                    })
+                    .and_then(|&(_, start, _, file_offset)| {
+                        file_address(
+                            elf.as_ref(),
+                            ip - start + file_offset,
+                        )
+                    });
+
+                if let Some(address) = address {
+                    *result.samples.entry(address).or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+
+        offset += size;
+    }
+
+    Ok(result)
+}
+
+/// Translates an offset in the ELF file `elf` to the virtual address at
+/// which it is loaded.
+fn file_address(
+    elf: Option<&elf::Elf>,
+    offset: usize,
+) -> Option<usize> {
+    elf?.program_headers
+        .iter()
+        .find(|x| {
+            x.p_type == elf::program_header::PT_LOAD
+                && x.file_range().contains(&offset)
+        })
+        .map(|x| offset - x.p_offset as usize + x.p_vaddr as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn script_lines() {
+        assert_eq!(
+            parse_script_line(
+                "foo 1234 [000] 1.5:     250000 cycles:u:  401136 \
+                 std::vector<int>::push_back(int const&)+0x16 \
+                 (/usr/bin/foo)"
+            ),
+            Some(ScriptSample {
+                ip: 0x401136,
+                symbol: Some((
+                    "std::vector<int>::push_back(int const&)",
+                    0x16
+                )),
+                dso: "/usr/bin/foo",
+            })
+        );
+        assert_eq!(
+            parse_script_line("\t    7f00a1 [unknown] ([unknown])"),
+            Some(ScriptSample {
+                ip: 0x7f00a1,
+                symbol: None,
+                dso: "[unknown]",
+            })
+        );
+        assert_eq!(parse_script_line("foo 1234 1.5: cycles:u:"), None);
+    }
+
+    fn record(kind: u32, body: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        result.extend(kind.to_le_bytes());
+        result.extend(0_u16.to_le_bytes());
+        result.extend((8 + body.len() as u16).to_le_bytes());
+        result.extend(body);
+
+        result
+    }
+
+    #[test]
+    fn perf_data() {
+        let path = "/usr/bin/app";
+        let data = crate::binary::test::elf_fixture();
+        let segment = elf::Elf::parse(&data)
+            .unwrap()
+            .program_headers
+            .into_iter()
+            .find(|x| {
+                x.p_type == elf::program_header::PT_LOAD
+                    && x.is_executable()
+            })
+            .unwrap();
+        let start = 0x7f00_0000_0000_u64;
+        let pid = 7_u64;
+        let mut mmap2 = (pid | pid << 32).to_le_bytes().to_vec();
+
+        for value in [start, segment.p_filesz, segment.p_offset] {
+            mmap2.extend(value.to_le_bytes());
+        }
+
+        mmap2.resize(72 - 8, 0);
+        mmap2.extend(path.as_bytes());
+        mmap2.resize(mmap2.len().next_multiple_of(8), 0);
+
+        let mut records = record(PERF_RECORD_MMAP2, &mmap2);
+
+        // The last sample is in another process that maps other code at
+        // the same address.
+        //
+        for (ip, pid) in [
+            (start + 0x10, pid),
+            (start + 0x10, pid),
+            (0xffff_ffff_8100_0000, pid),
+            (start + 0x10, pid + 1),
+        ] {
+            let sample: Vec<u8> = [42_u64, ip, pid | pid << 32]
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect();
+
+            records.extend(record(PERF_RECORD_SAMPLE, &sample));
+        }
+
+        let mut attr = vec![0; SAMPLE_TYPE_OFFSET];
+
+        attr.extend(
+            (PERF_SAMPLE_IDENTIFIER | PERF_SAMPLE_IP | PERF_SAMPLE_TID)
+                .to_le_bytes(),
+        );
+
+        let mut profile = PERF_MAGIC.to_vec();
+        let attr_offset = PERF_HEADER_SIZE;
+        let data_offset = attr_offset + attr.len();
+
+        for value in [
+            PERF_HEADER_SIZE,
+            attr.len(),
+            attr_offset,
+            attr.len(),
+            data_offset,
+            records.len(),
+        ] {
+            profile.extend((value as u64).to_le_bytes());
+        }
+
+        profile.resize(PERF_HEADER_SIZE, 0);
+        profile.extend(attr);
+        profile.extend(records);
+
+        let name = "app";
+
+        assert_eq!(
+            read_perf_data(&profile, name, &data).unwrap(),
+            Profile {
+                samples: BTreeMap::from([(
+                    segment.p_vaddr as usize + 0x10,
+                    2
+                )]),
+                total: 4,
+            }
+        );
+        assert!(read_perf_data(
+            &profile[..profile.len() - 1],
+            name,
+            &data
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn script() {
+        #[rustfmt::skip]
+        let code: &[u8] = &[
+            0x0f, 0x28, 0x06,        // movaps xmm0,XMMWORD PTR [rsi]
+            0xc3,                    // ret
+        ];
+        let binary = Binary::from_raw(
+            code,
+            0x400,
+            64,
+            &[
+                (0x400, "_Z3sseIiEvPKT_".to_string()),
+                (0x403, "ret@@VERS_1".to_string()),
+            ],
+            0,
+            &[],
+        )
+        .unwrap();
+        // The binary is position-independent and loaded at 0x55d000.
+        //
+        let text = "\
+foo 1 1.0: cycles:u: 55d403 ret+0x0 (/usr/bin/foo)
+foo 1 1.1: cycles:u: 55d401 [unknown] (/usr/bin/foo)
+foo 1 1.2: cycles:u: 7f00a1 memcpy+0x1 (/usr/lib/libc.so.6)
+foo 1 1.3: cycles:u:
+\t55d400 void sse<int>(int const*)+0x0 (/usr/bin/foo)
+\t55d500 main+0x10 (/usr/bin/foo)
+
+";
+
+        assert_eq!(
+            read_perf_script(text, "foo", &binary),
+            Profile {
+                samples: BTreeMap::from([
+                    (0x400, 1),
+                    (0x401, 1),
+                    (0x403, 1)
+                ]),
+                total: 4,
+            }
+        );
+
+        // Without resolved symbols, addresses are taken as is.
+        //
+        let text =
+            "foo 1 1.0: cycles:u: 400 [unknown] (/usr/bin/foo)\n";
+
+        assert_eq!(
+            read_perf_script(text, "foo", &binary).samples,
+            BTreeMap::from([(0x400, 1)])
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::Context;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use wildmatch::WildMatch;
+
+use crate::binary;
+use crate::compression;
+use crate::jit;
+use crate::package::{self, PackageKind};
+
+/// Criteria for the files that are inspected in directories.
+pub struct ScanOptions<'a> {
+    /// Only inspect files whose name or path matches one of these
+    /// patterns.  All files are inspected if this is empty.
+    pub include: &'a [WildMatch],
+    /// Skip files and directories whose name or path matches one of
+    /// these patterns.
+    pub exclude: &'a [WildMatch],
+    /// Follow symbolic links to files and directories.  Without this,
+    /// symbolic links inside directories are skipped so that libraries
+    /// such as `libfoo.so -> libfoo.so.1` are only inspected once.
+    pub follow_symlinks: bool,
+}
+
+/// Formats of the files that can be inspected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Binary,
+    Package(PackageKind),
+}
+
+/// Returns the x86 ELF, PE, and Mach-O files, jitdump files, and
+/// packages among `paths` and, recursively, inside the directories
+/// among `paths`.
+///
+/// Files inside directories that are neither x86 binaries nor packages
+/// are skipped silently.  Files given explicitly are skipped with a
+/// warning.
+pub fn find_inputs(
+    paths: &[String],
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<(PathBuf, InputKind)>> {
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+
+    for path in paths.iter().map(Path::new) {
+        if !path.is_dir() {
+            match input_kind(path) {
+                None => eprintln!(
+                    "Skipping '{}': not an x86 binary or a package",
+                    path.display()
+                ),
+                Some(kind) => {
+                    if seen.insert(canonical_path(path)) {
+                        result.push((path.to_path_buf(), kind));
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        let entries = WalkDir::new(path)
+            .follow_links(options.follow_symlinks)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || !matches_any(options.exclude, entry.path())
+            });
+
+        for entry in entries {
+            let entry = entry.with_context(|| {
+                format!("Could not scan '{}'", path.display())
+            })?;
+
+            if !entry.file_type().is_file()
+                || (!options.include.is_empty()
+                    && !matches_any(options.include, entry.path()))
+            {
+                continue;
+            }
+
+            let Some(kind) = input_kind(entry.path()) else {
+                continue;
+            };
+
+            // Two paths can refer to the same file if symbolic links
+            // are followed.
+            //
+            if seen.insert(canonical_path(entry.path())) {
+                result.push((entry.into_path(), kind));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether any pattern matches either the file name or the full path.
+fn matches_any(patterns: &[WildMatch], path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default();
+    let path = path.to_string_lossy();
+
+    patterns
+        .iter()
+        .any(|pattern| pattern.matches(&name) || pattern.matches(&path))
+}
+
+/// Detects the format of `path` from its first bytes, after
+/// decompressing it if necessary.
+pub fn input_kind(path: &Path) -> Option<InputKind> {
+    let mut header = binary::read_header(path)?;
+
+    // Compressed files are decompressed as a whole, since they are
+    // typically small or archives that have to be read anyway.
+    //
+    if compression::is_compressed(&header) {
+        header = compression::read_file(path).ok()?;
+    }
+
+    if binary::is_x86_elf(&header) || jit::is_jitdump(&header) {
+        Some(InputKind::Binary)
+    } else if binary::is_pe_or_mach(&header) {
+        compression::read_file(path)
+            .is_ok_and(|data| binary::is_x86_object(&data))
+            .then_some(InputKind::Binary)
+    } else {
+        package::package_kind(&header).map(InputKind::Package)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let patterns =
+            [WildMatch::new("*.so*"), WildMatch::new("*/bin/*")];
+
+        assert!(matches_any(
+            &patterns,
+            Path::new("/usr/lib/libm.so.6")
+        ));
+        assert!(matches_any(&patterns, Path::new("/usr/bin/ls")));
+        assert!(!matches_any(&patterns, Path::new("/usr/lib/crt1.o")));
+        assert!(!matches_any(&[], Path::new("/usr/bin/ls")));
+    }
+}